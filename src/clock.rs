@@ -0,0 +1,126 @@
+use std::fmt;
+use std::time::Duration;
+
+// Time given back for each move.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Bonus {
+    None,
+    // Added after every move.
+    Fischer(Duration),
+    // The clock only starts once this much of the move has gone by.
+    Bronstein(Duration),
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub bonus: Bonus,
+    // Moves per period, after which `base` is added again; 0 for sudden death.
+    pub moves: u32,
+}
+
+impl TimeControl {
+    // `[moves/]seconds[+increment|ddelay]`, as in "300", "180+2", "300d5" or "40/5400+30".
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid time control {}", s);
+        let secs = |t: &str| t.parse().ok().and_then(|t| Duration::try_from_secs_f64(t).ok()).ok_or_else(invalid);
+
+        let (moves, rest) = match s.split_once('/') {
+            Some((moves, rest)) => (moves.parse().map_err(|_| invalid())?, rest),
+            None => (0, s),
+        };
+
+        let (base, bonus) = if let Some((base, increment)) = rest.split_once('+') {
+            (secs(base)?, Bonus::Fischer(secs(increment)?))
+        } else if let Some((base, delay)) = rest.split_once('d') {
+            (secs(base)?, Bonus::Bronstein(secs(delay)?))
+        } else {
+            (secs(rest)?, Bonus::None)
+        };
+
+        if base.is_zero() {return Err(invalid())}
+        Ok(TimeControl { base, bonus, moves })
+    }
+
+    // The standard PGN TimeControl tag, "300", "180+2" or "40/5400", plus a TimeDelay or
+    // TimeIncrement tag for a bonus the standard form has no way to write.
+    pub fn tags(&self) -> Vec<(&'static str, String)> {
+        let secs = |t: Duration| t.as_secs_f64().to_string();
+        let base = secs(self.base);
+
+        match (self.moves, self.bonus) {
+            (0, Bonus::Fischer(t)) => vec![("TimeControl", format!("{}+{}", base, secs(t)))],
+            (0, _) => vec![("TimeControl", base)],
+            (moves, _) => vec![("TimeControl", format!("{}/{}", moves, base))],
+        }.into_iter().chain(match (self.moves, self.bonus) {
+            (_, Bonus::Bronstein(t)) => Some(("TimeDelay", secs(t))),
+            (1.., Bonus::Fischer(t)) => Some(("TimeIncrement", secs(t))),
+            _ => None,
+        }).collect()
+    }
+
+    // Reads back what `tags` writes. Of a TimeControl tag with several periods only the first is
+    // kept, as later periods are taken to repeat it.
+    pub fn from_tags<'a>(tag: impl Fn(&str) -> Option<&'a str>) -> Option<Self> {
+        let first = tag("TimeControl")?.split(':').next()?;
+        if first.contains('d') {return None}
+
+        let mut tc = TimeControl::parse(first).ok()?;
+        let secs = |name| tag(name).and_then(|t| t.parse().ok()).and_then(|t| Duration::try_from_secs_f64(t).ok());
+
+        if let Some(delay) = secs("TimeDelay") {tc.bonus = Bonus::Bronstein(delay)}
+        if let Some(increment) = secs("TimeIncrement").filter(|_| tc.bonus == Bonus::None) {tc.bonus = Bonus::Fischer(increment)}
+        Some(tc)
+    }
+
+    // Time left for a player whose moves took `spent`, in order, after `running` on the move they
+    // are on now. Zero once their flag has fallen.
+    pub fn left(&self, spent: &[Duration], running: Duration) -> Duration {
+        let mut left = self.base;
+
+        for (i, &t) in spent.iter().chain([&running]).enumerate() {
+            let delay = match self.bonus {Bonus::Bronstein(delay) => t.min(delay), _ => Duration::ZERO};
+            if t >= left + delay {return Duration::ZERO}
+            left = left + delay - t;
+
+            if i == spent.len() {break}
+            if let Bonus::Fischer(increment) = self.bonus {left += increment}
+            if (i as u32 + 1).is_multiple_of(self.moves) {left += self.base}
+        }
+
+        left
+    }
+
+    // How long to think with `left` on the clock after `played` moves: an even share of the time
+    // until the next period, or of about 30 more moves, plus most of the bonus.
+    pub fn budget(&self, left: Duration, played: usize) -> Duration {
+        let to_go = if self.moves > 0 {self.moves - played as u32 % self.moves} else {30};
+        let bonus = match self.bonus {
+            Bonus::Fischer(t) | Bonus::Bronstein(t) => t,
+            Bonus::None => Duration::ZERO,
+        };
+
+        (left / to_go + bonus * 3 / 4).min(left / 2)
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.moves > 0 {write!(f, "{}/", self.moves)?}
+        write!(f, "{}", self.base.as_secs_f64())?;
+
+        match self.bonus {
+            Bonus::None => Ok(()),
+            Bonus::Fischer(t) => write!(f, "+{}", t.as_secs_f64()),
+            Bonus::Bronstein(t) => write!(f, "d{}", t.as_secs_f64()),
+        }
+    }
+}
+
+// `m:ss`, or `h:mm:ss` from an hour up, with tenths under ten seconds.
+pub fn format(t: Duration) -> String {
+    let secs = t.as_secs();
+    if secs < 10 {return format!("0:0{}.{}", secs, t.subsec_millis() / 100)}
+    if secs >= 3600 {return format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)}
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
@@ -0,0 +1,198 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::fen;
+use crate::position::Position;
+use crate::search::{self, Limits};
+
+const MAX_PLIES: usize = 400;
+
+struct Options {
+    games: usize,
+    nodes: u64,
+    threads: usize,
+    random_plies: usize,
+    out: String,
+    text: bool,
+}
+
+struct Record {
+    pos: Position,
+    score: i32,
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut o = Options {
+        games: 100,
+        nodes: 5000,
+        threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        random_plies: 8,
+        out: String::new(),
+        text: false,
+    };
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).ok_or(format!("{} needs a value", args[i]))?;
+        let number = || value.parse::<u64>().map_err(|_| format!("{} needs a number", args[i]));
+
+        match args[i].as_str() {
+            "--games" => o.games = number()? as usize,
+            "--nodes" => o.nodes = number()?,
+            "--threads" => o.threads = number()?.max(1) as usize,
+            "--random-plies" => o.random_plies = number()? as usize,
+            "--out" => o.out = value.clone(),
+            "--format" => match value.as_str() {
+                "binary" => o.text = false,
+                "text" => o.text = true,
+                f => return Err(format!("unknown format {}, expected binary or text", f)),
+            },
+            other => return Err(format!("unexpected argument {}", other)),
+        }
+        i += 2;
+    }
+
+    if o.out.is_empty() {
+        o.out = if o.text {"datagen.txt"} else {"datagen.bin"}.to_string();
+    }
+
+    let file = File::create(&o.out).map_err(|e| format!("{}: {}", o.out, e))?;
+    let out = Mutex::new(BufWriter::new(file));
+    let started = AtomicUsize::new(0);
+    let positions = AtomicUsize::new(0);
+
+    // A thread that fails to write ends the run: the games left are claimed so the others stop too.
+    let results: Vec<io::Result<()>> = std::thread::scope(|scope| {
+        let threads: Vec<_> = (0..o.threads).map(|t| {
+            let (o, out, started, positions) = (&o, &out, &started, &positions);
+
+            scope.spawn(move || {
+                let mut rng = Rng::new(t as u64);
+
+                while started.fetch_add(1, Ordering::Relaxed) < o.games {
+                    let (records, result) = play_game(o, &mut rng);
+
+                    let mut out = out.lock().unwrap();
+                    let written = records.iter()
+                        .try_for_each(|r| if o.text {write_text(&mut *out, r, result)} else {write_binary(&mut *out, r, result)})
+                        .and_then(|_| out.flush());
+                    if written.is_err() {started.store(o.games, Ordering::Relaxed)}
+                    written?;
+
+                    let total = positions.fetch_add(records.len(), Ordering::Relaxed) + records.len();
+                    println!("game finished: {} positions, result {}, total {}", records.len(), result, total);
+                }
+                Ok(())
+            })
+        }).collect();
+
+        threads.into_iter().map(|t| t.join().unwrap()).collect()
+    });
+
+    if let Some(Err(e)) = results.into_iter().find(Result::is_err) {
+        return Err(format!("{}: {}", o.out, e));
+    }
+
+    println!("wrote {} positions to {}", positions.load(Ordering::Relaxed), o.out);
+
+    Ok(())
+}
+
+// Returns the recorded positions and the result from white's point of view (1, 0.5 or 0).
+fn play_game(o: &Options, rng: &mut Rng) -> (Vec<Record>, f32) {
+    'retry: loop {
+        let mut pos = Position::start();
+        let mut history = Vec::new();
+
+        for _ in 0..o.random_plies {
+            let moves = pos.legal_moves();
+            if moves.is_empty() {continue 'retry}
+
+            history.push(pos.hash());
+            pos = pos.make(moves[rng.next() as usize % moves.len()]);
+        }
+
+        if pos.legal_moves().is_empty() {continue}
+
+        let mut records = Vec::new();
+
+        for _ in 0..MAX_PLIES {
+            let moves = pos.legal_moves();
+
+            if moves.is_empty() {
+                let result = if !pos.in_check(pos.white) {0.5} else if pos.white {0.} else {1.};
+                return (records, result);
+            }
+
+            let hash = pos.hash();
+            if pos.halfmove >= 100 || pos.insufficient_material() || history.iter().filter(|&&h| h == hash).count() >= 2 {
+                return (records, 0.5);
+            }
+
+            let r = search::search(&pos, &history, Limits::nodes(o.nodes));
+            let Some(m) = r.best else {break};
+
+            if !search::is_mate_score(r.score) && !pos.is_capture(m) && !pos.is_promotion(m) && search::is_quiet(&pos) {
+                records.push(Record {
+                    pos,
+                    score: if pos.white {r.score} else {-r.score},
+                });
+            }
+
+            history.push(hash);
+            pos = pos.make(m);
+        }
+
+        return (records, 0.5);
+    }
+}
+
+// `<fen> [<result>] <score>`: readable by `chess tune`, which takes the bracketed result.
+fn write_text(out: &mut impl Write, r: &Record, result: f32) -> io::Result<()> {
+    writeln!(out, "{} [{:.1}] {}", fen::to_fen(&r.pos), result, r.score)
+}
+
+// 28 bytes per position: occupancy bitboard (u64, bit y * 8 + x), one nibble per occupied
+// square in bit order (kind index, +8 for black), side to move, score (i16, white's point of
+// view) and result (0 black win, 1 draw, 2 white win), all little-endian.
+fn write_binary(out: &mut impl Write, r: &Record, result: f32) -> io::Result<()> {
+    let mut occupancy = 0u64;
+    let mut nibbles = [0u8; 16];
+    let mut n = 0;
+
+    for (i, square) in r.pos.board.iter().flatten().enumerate() {
+        if let Some(p) = square {
+            occupancy |= 1 << i;
+            let code = p.kind.index() as u8 | if p.white {0} else {8};
+            nibbles[n / 2] |= code << (4 * (n % 2));
+            n += 1;
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(28);
+    bytes.extend_from_slice(&occupancy.to_le_bytes());
+    bytes.extend_from_slice(&nibbles);
+    bytes.push(r.pos.white as u8);
+    bytes.extend_from_slice(&(r.score.clamp(i16::MIN as i32, i16::MAX as i32) as i16).to_le_bytes());
+    bytes.push((result * 2.) as u8);
+
+    out.write_all(&bytes)
+}
+
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(stream: u64) -> Self {
+        let time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        Rng((time ^ stream.wrapping_mul(0x9E37_79B9_7F4A_7C15)) | 1)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
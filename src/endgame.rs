@@ -0,0 +1,282 @@
+use std::sync::OnceLock;
+
+use crate::{Kind, Piece};
+
+type Board = [[Option<Piece>; 8]; 8];
+type Square = (usize, usize);
+
+// Returned for won endings so that simplifying into one beats keeping extra material.
+const KNOWN_WIN: f32 = 50.;
+
+// Specialised evaluators score from the strong side's point of view.
+type Evaluator = fn(&Ending) -> f32;
+
+struct Ending<'a> {
+    board: &'a Board,
+    strong: bool,
+    strong_to_move: bool,
+}
+
+impl Ending<'_> {
+    // Squares are mirrored vertically when black is the strong side, so that the strong side
+    // always plays up the board (towards y = 0).
+    fn find(&self, kind: Kind, strong: bool) -> Vec<Square> {
+        let white = strong == self.strong;
+        let mut squares = Vec::new();
+
+        for (y, row) in self.board.iter().enumerate() {
+            for (x, square) in row.iter().enumerate() {
+                if matches!(square, Some(p) if p.kind == kind && p.white == white) {
+                    squares.push((x, if self.strong {y} else {7 - y}));
+                }
+            }
+        }
+
+        squares
+    }
+
+    fn king(&self, strong: bool) -> Square {
+        self.find(Kind::King, strong)[0]
+    }
+}
+
+pub fn material(board: &Board, white: bool) -> String {
+    let mut s = String::new();
+
+    for (kind, c) in [(Kind::King, 'K'), (Kind::Queen, 'Q'), (Kind::Rook, 'R'), (Kind::Bishop, 'B'), (Kind::Knight, 'N'), (Kind::Pawn, 'P')] {
+        let n = board.iter().flatten().flatten().filter(|p| p.kind == kind && p.white == white).count();
+        s.extend(std::iter::repeat_n(c, n));
+    }
+
+    s
+}
+
+fn specialised(signature: &str) -> Option<Evaluator> {
+    match signature {
+        "KPvK" => Some(kpk),
+        "KBNvK" => Some(kbnk),
+        "KQvK" | "KRvK" => Some(kxk),
+        "KRvKP" => Some(krkp),
+        _ => None,
+    }
+}
+
+// Recognised endings replace `eval` (white's point of view); otherwise it is scaled towards a
+// draw when the side ahead cannot realistically win.
+pub fn evaluate(board: &Board, white_to_move: bool, eval: impl FnOnce() -> f32) -> f32 {
+    let mut counts = [[0; 6]; 2];
+    for p in board.iter().flatten().flatten() {
+        counts[!p.white as usize][p.kind.index()] += 1;
+    }
+
+    if counts.iter().flatten().sum::<usize>() <= 5 {
+        let (w, b) = (material(board, true), material(board, false));
+
+        for strong in [true, false] {
+            let signature = if strong {format!("{}v{}", w, b)} else {format!("{}v{}", b, w)};
+
+            if let Some(f) = specialised(&signature) {
+                let v = f(&Ending { board, strong, strong_to_move: strong == white_to_move });
+                return if strong {v} else {-v};
+            }
+        }
+    }
+
+    let eval = eval();
+    eval * scale(board, &counts, eval > 0.)
+}
+
+fn scale(board: &Board, counts: &[[usize; 6]; 2], strong: bool) -> f32 {
+    let [pawn, knight, bishop, rook, queen, _] = counts[!strong as usize];
+    let weak = counts[strong as usize];
+
+    // A single minor piece without pawns cannot mate.
+    if pawn == 0 && rook + queen == 0 && knight + bishop <= 1 {return 0.}
+
+    let bishops: Vec<(Square, bool)> = board.iter().enumerate().flat_map(|(y, row)| {
+        row.iter().enumerate().filter_map(move |(x, s)| match s {
+            Some(p) if p.kind == Kind::Bishop => Some(((x, y), p.white)),
+            _ => None,
+        })
+    }).collect();
+    let light = |(x, y): Square| (x + y) % 2 == 0;
+
+    // Bishop and rook pawns against a bare king that holds the wrong-coloured corner.
+    if knight + rook + queen == 0 && bishop == 1 && pawn > 0 && weak[..5].iter().all(|&n| n == 0) {
+        let files: Vec<usize> = board.iter().flat_map(|row| row.iter().enumerate())
+            .filter(|(_, s)| matches!(s, Some(p) if p.kind == Kind::Pawn))
+            .map(|(x, _)| x)
+            .collect();
+
+        if files.iter().all(|&x| x == files[0]) && (files[0] == 0 || files[0] == 7) {
+            let corner = (files[0], if strong {0} else {7});
+            let (bishop_sq, _) = bishops[0];
+
+            let weak_king = board.iter().enumerate().flat_map(|(y, row)| row.iter().enumerate().map(move |(x, s)| ((x, y), s)))
+                .find(|(_, s)| matches!(s, Some(p) if p.kind == Kind::King && p.white != strong))
+                .map(|(sq, _)| sq);
+
+            if light(bishop_sq) != light(corner) && weak_king.is_some_and(|k| distance(k, corner) <= 1) {return 0.}
+        }
+    }
+
+    if bishop == 1 && weak[2] == 1 && bishops.len() == 2 && light(bishops[0].0) != light(bishops[1].0) {
+        let pieces_only_bishops = knight + rook + queen == 0 && weak[1] + weak[3] + weak[4] == 0;
+        return if pieces_only_bishops {0.5} else {0.8};
+    }
+
+    1.
+}
+
+fn distance(a: Square, b: Square) -> usize {
+    a.0.abs_diff(b.0).max(a.1.abs_diff(b.1))
+}
+
+// Grows from 1 in the centre to 7 in the corners.
+fn edge(sq: Square) -> f32 {
+    ((2 * sq.0 as i32 - 7).abs() + (2 * sq.1 as i32 - 7).abs()) as f32 / 2.
+}
+
+fn close(a: Square, b: Square) -> f32 {
+    (7 - distance(a, b)) as f32
+}
+
+fn kxk(e: &Ending) -> f32 {
+    let weak_king = e.king(false);
+    let heavy = if e.find(Kind::Queen, true).is_empty() {5.} else {9.};
+
+    KNOWN_WIN + heavy + edge(weak_king) * 0.5 + close(e.king(true), weak_king) * 0.3
+}
+
+fn kbnk(e: &Ending) -> f32 {
+    let weak_king = e.king(false);
+    let (bx, by) = e.find(Kind::Bishop, true)[0];
+
+    let corners = if (bx + by) % 2 == 0 {[(0, 0), (7, 7)]} else {[(7, 0), (0, 7)]};
+    let corner = corners.iter().map(|&c| distance(weak_king, c)).min().unwrap();
+
+    KNOWN_WIN + 6. + (7 - corner) as f32 * 0.5 + close(e.king(true), weak_king) * 0.3
+}
+
+fn kpk(e: &Ending) -> f32 {
+    let (mut wk, mut bk, mut p) = (e.king(true), e.king(false), e.find(Kind::Pawn, true)[0]);
+
+    if p.0 > 3 {
+        for sq in [&mut wk, &mut bk, &mut p] {sq.0 = 7 - sq.0}
+    }
+
+    if kpk_win(e.strong_to_move, wk, bk, p) {
+        KNOWN_WIN + 1. + (7 - p.1) as f32 * 0.1
+    } else {
+        0.
+    }
+}
+
+fn krkp(e: &Ending) -> f32 {
+    let (strong_king, weak_king) = (e.king(true), e.king(false));
+    let rook = e.find(Kind::Rook, true)[0];
+    let pawn = e.find(Kind::Pawn, false)[0];
+    let queening = (pawn.0, 7);
+
+    // Relative ranks from the strong side's point of view: 0 is its own back rank.
+    let rank = |sq: Square| 7 - sq.1;
+
+    // Won when the strong king blocks the pawn, or the weak king is too far from pawn and rook.
+    let blocked = strong_king.0 == pawn.0 && strong_king.1 > pawn.1;
+    let far = distance(weak_king, pawn) >= 3 + !e.strong_to_move as usize && distance(weak_king, rook) >= 3;
+
+    if blocked || far {
+        5. - distance(strong_king, pawn) as f32 * 0.1
+    } else if rank(weak_king) <= 2 && distance(weak_king, pawn) == 1 && rank(strong_king) >= 3
+        && distance(strong_king, pawn) > 2 + e.strong_to_move as usize {
+        0.4 - distance(strong_king, pawn) as f32 * 0.04
+    } else {
+        let d = distance(strong_king, queening) as f32 - distance(weak_king, queening) as f32 - distance(pawn, queening) as f32;
+        1. - d * 0.04
+    }
+}
+
+// KPK bitbase, generated by retrograde iteration with the pawn side as white on files a-d.
+// Index: side to move, defending king, attacking king, pawn (files a-d, ranks 7 to 2).
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+fn kpk_index(white_to_move: bool, bk: Square, wk: Square, p: Square) -> usize {
+    ((!white_to_move as usize * 64 + bk.1 * 8 + bk.0) * 64 + wk.1 * 8 + wk.0) * 24 + (p.1 - 1) * 4 + p.0
+}
+
+fn kpk_win(white_to_move: bool, wk: Square, bk: Square, p: Square) -> bool {
+    static KPK: OnceLock<Vec<bool>> = OnceLock::new();
+
+    let table = KPK.get_or_init(|| {
+        let decode = |i: usize| {
+            let p = (i % 24 % 4, i % 24 / 4 + 1);
+            let wk = (i / 24 % 8, i / 24 / 8 % 8);
+            let bk = (i / 24 / 64 % 8, i / 24 / 64 / 8 % 8);
+            (i / (24 * 64 * 64) == 0, wk, bk, p)
+        };
+
+        let mut db: Vec<u8> = (0..2 * 64 * 64 * 24).map(|i| {
+            let (white, wk, bk, p) = decode(i);
+            kpk_initial(white, wk, bk, p)
+        }).collect();
+
+        loop {
+            let mut changed = false;
+
+            for i in 0..db.len() {
+                if db[i] != UNKNOWN {continue}
+
+                let (white, wk, bk, p) = decode(i);
+                let mut r = 0;
+
+                if white {
+                    for s in king_moves(wk) {r |= db[kpk_index(false, bk, s, p)]}
+
+                    if p.1 > 1 {r |= db[kpk_index(false, bk, wk, (p.0, p.1 - 1))]}
+                    if p.1 == 6 && (p.0, 5) != wk && (p.0, 5) != bk {r |= db[kpk_index(false, bk, wk, (p.0, 4))]}
+
+                    r = if r & WIN != 0 {WIN} else if r & UNKNOWN != 0 {UNKNOWN} else {DRAW};
+                } else {
+                    for s in king_moves(bk) {r |= db[kpk_index(true, s, wk, p)]}
+
+                    r = if r & DRAW != 0 {DRAW} else if r & UNKNOWN != 0 {UNKNOWN} else {WIN};
+                }
+
+                if r != UNKNOWN {
+                    db[i] = r;
+                    changed = true;
+                }
+            }
+
+            if !changed {break}
+        }
+
+        db.iter().map(|&r| r == WIN).collect()
+    });
+
+    table[kpk_index(white_to_move, bk, wk, p)]
+}
+
+fn kpk_initial(white: bool, wk: Square, bk: Square, p: Square) -> u8 {
+    let pawn_attacks = |s: Square| s.1 + 1 == p.1 && s.0.abs_diff(p.0) == 1;
+    let queening = (p.0, p.1 - 1);
+
+    if distance(wk, bk) <= 1 || wk == p || bk == p || (white && pawn_attacks(bk)) {
+        0
+    } else if white && p.1 == 1 && wk != queening && (distance(bk, queening) > 1 || distance(wk, queening) == 1) {
+        WIN
+    } else if !white && (king_moves(bk).all(|s| distance(s, wk) <= 1 || pawn_attacks(s))
+        || (distance(bk, p) == 1 && distance(wk, p) > 1)) {
+        DRAW
+    } else {
+        UNKNOWN
+    }
+}
+
+fn king_moves((x, y): Square) -> impl Iterator<Item = Square> {
+    (-1..=1).flat_map(move |dy| (-1..=1).map(move |dx| (x as isize + dx, y as isize + dy)))
+        .filter(move |&(tx, ty)| (0..8).contains(&tx) && (0..8).contains(&ty) && (tx, ty) != (x as isize, y as isize))
+        .map(|(tx, ty)| (tx as usize, ty as usize))
+}
@@ -0,0 +1,156 @@
+use crate::position::Position;
+use crate::{Kind, Piece};
+
+pub const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+pub fn parse_board(fen: &str) -> Result<[[Option<Piece>; 8]; 8], String> {
+    let placement = fen.split_whitespace().next().ok_or("empty FEN")?;
+
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(format!("expected 8 ranks, found {}", ranks.len()));
+    }
+
+    let mut board = [[None; 8]; 8];
+
+    for (y, rank) in ranks.iter().enumerate() {
+        let mut x = 0;
+
+        for c in rank.chars() {
+            if let Some(n) = c.to_digit(10) {
+                x += n as usize;
+            } else {
+                let kind = match c.to_ascii_lowercase() {
+                    'p' => Kind::Pawn,
+                    'n' => Kind::Knight,
+                    'b' => Kind::Bishop,
+                    'r' => Kind::Rook,
+                    'q' => Kind::Queen,
+                    'k' => Kind::King,
+                    _ => return Err(format!("invalid piece '{}' on rank {}", c, 8 - y)),
+                };
+
+                if x > 7 {
+                    return Err(format!("too many squares on rank {}", 8 - y));
+                }
+
+                board[y][x] = Some(Piece {
+                    kind,
+                    white: c.is_ascii_uppercase(),
+                });
+                x += 1;
+            }
+        }
+
+        if x != 8 {
+            return Err(format!("rank {} has {} squares", 8 - y, x));
+        }
+    }
+
+    Ok(board)
+}
+
+pub fn parse(fen: &str) -> Result<Position, String> {
+    let board = parse_board(fen)?;
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+
+    let white = match fields.get(1).copied().unwrap_or("w") {
+        "w" => true,
+        "b" => false,
+        s => return Err(format!("invalid side to move '{}'", s)),
+    };
+
+    let mut castling = [false; 4];
+    let rights = fields.get(2).copied().unwrap_or("-");
+    if rights != "-" {
+        for c in rights.chars() {
+            match c {
+                'K' => castling[0] = true,
+                'Q' => castling[1] = true,
+                'k' => castling[2] = true,
+                'q' => castling[3] = true,
+                _ => return Err(format!("invalid castling rights '{}'", rights)),
+            }
+        }
+    }
+
+    let ep = match fields.get(3).copied().unwrap_or("-") {
+        "-" => None,
+        s => Some(parse_square(s).filter(|&(_, y)| y == 2 || y == 5).ok_or(format!("invalid en passant square '{}'", s))?),
+    };
+
+    let halfmove = fields.get(4).map_or(Ok(0), |s| s.parse()).map_err(|_| "invalid halfmove clock".to_string())?;
+    let fullmove = fields.get(5).map_or(Ok(1), |s| s.parse()).map_err(|_| "invalid fullmove number".to_string())?;
+
+    Ok(Position {
+        board,
+        white,
+        castling,
+        ep,
+        halfmove,
+        fullmove,
+    })
+}
+
+pub fn to_fen(pos: &Position) -> String {
+    let mut fen = String::new();
+
+    for (y, row) in pos.board.iter().enumerate() {
+        let mut empty = 0;
+
+        for square in row {
+            match square {
+                Some(p) => {
+                    if empty > 0 {
+                        fen += &empty.to_string();
+                        empty = 0;
+                    }
+                    fen.push(piece_char(*p));
+                },
+                None => empty += 1,
+            }
+        }
+
+        if empty > 0 {fen += &empty.to_string()}
+        if y < 7 {fen.push('/')}
+    }
+
+    fen += if pos.white {" w "} else {" b "};
+
+    let rights: String = "KQkq".chars().zip(pos.castling).filter(|(_, on)| *on).map(|(c, _)| c).collect();
+    fen += if rights.is_empty() {"-"} else {&rights};
+
+    match pos.ep {
+        Some(sq) => fen += &format!(" {}", square_name(sq)),
+        None => fen += " -",
+    }
+
+    fen + &format!(" {} {}", pos.halfmove, pos.fullmove)
+}
+
+pub fn piece_char(piece: Piece) -> char {
+    let c = match piece.kind {
+        Kind::Pawn => 'p',
+        Kind::Knight => 'n',
+        Kind::Bishop => 'b',
+        Kind::Rook => 'r',
+        Kind::Queen => 'q',
+        Kind::King => 'k',
+    };
+
+    if piece.white {c.to_ascii_uppercase()} else {c}
+}
+
+pub fn parse_square(s: &str) -> Option<(usize, usize)> {
+    let mut chars = s.chars();
+    let file = chars.next()?;
+    let rank = chars.next()?;
+
+    if chars.next().is_some() || !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {return None}
+
+    Some((file as usize - 'a' as usize, 8 - (rank as usize - '0' as usize)))
+}
+
+pub fn square_name((x, y): (usize, usize)) -> String {
+    format!("{}{}", (b'a' + x as u8) as char, 8 - y)
+}
@@ -0,0 +1,376 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::clock::TimeControl;
+use crate::fen;
+use crate::pgn;
+use crate::position::{Move, Position};
+use crate::san;
+
+pub struct Record {
+    pub m: Move,
+    pub san: String,
+    // Time the mover spent on the move.
+    pub elapsed: Duration,
+    // Engine evaluation after the move, in pawns from white's point of view.
+    pub eval: Option<f32>,
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+}
+
+impl Record {
+    fn new(pos: &Position, m: Move, elapsed: Duration, eval: Option<f32>) -> Self {
+        let san = san::format(pos, m, &pos.make(m));
+        Record { m, san, elapsed, eval, nags: Vec::new(), comment: None }
+    }
+}
+
+// Moves played instead of the main line from ply `ply` on, with side lines of their own.
+// `ply` counts from the start of the game in both cases.
+pub struct Variation {
+    pub ply: usize,
+    pub moves: Vec<Record>,
+    pub variations: Vec<Variation>,
+}
+
+// A game from its starting position: `positions[i]` is the position before `moves[i]`.
+pub struct Game {
+    pub event: String,
+    pub site: String,
+    pub date: String,
+    pub round: String,
+    pub white: String,
+    pub black: String,
+    pub result: Option<String>,
+    pub time_control: Option<TimeControl>,
+    pub positions: Vec<Position>,
+    pub moves: Vec<Record>,
+    pub variations: Vec<Variation>,
+    // Moves taken back with `undo`, the latest last, until another move is played.
+    pub undone: Vec<Record>,
+}
+
+impl Game {
+    pub fn new(start: Position, white: &str, black: &str) -> Self {
+        Game {
+            event: "Casual game".to_string(),
+            site: "?".to_string(),
+            date: today(),
+            round: "-".to_string(),
+            white: white.to_string(),
+            black: black.to_string(),
+            result: None,
+            time_control: None,
+            positions: vec![start],
+            moves: Vec::new(),
+            variations: Vec::new(),
+            undone: Vec::new(),
+        }
+    }
+
+    // Replays `g`, variations included, through the legal move generator.
+    pub fn from_pgn(g: &pgn::Game) -> Result<Game, String> {
+        let start = match g.tag("FEN") {
+            Some(f) => fen::parse(f).and_then(|p| p.validate().map(|_| p)).map_err(|e| format!("invalid FEN tag: {}", e))?,
+            None => Position::start(),
+        };
+
+        let mut game = Game::new(start, g.tag("White").unwrap_or("?"), g.tag("Black").unwrap_or("?"));
+        game.date = "????.??.??".to_string();
+        for (name, field) in [("Event", &mut game.event), ("Site", &mut game.site), ("Date", &mut game.date), ("Round", &mut game.round)] {
+            if let Some(value) = g.tag(name) {*field = value.to_string()}
+        }
+        game.time_control = TimeControl::from_tags(|name| g.tag(name));
+
+        let (moves, variations) = line(start, 0, &g.moves)?;
+        for record in moves {
+            let next = game.position().make(record.m);
+            game.positions.push(next);
+            game.moves.push(record);
+        }
+        game.variations = variations;
+        game.result = game.outcome();
+
+        if g.result != "*" {game.result = Some(g.result.clone())}
+        Ok(game)
+    }
+
+    pub fn position(&self) -> &Position {
+        self.positions.last().unwrap()
+    }
+
+    // Drops every move after the first `plies`, so that play can continue from there.
+    pub fn truncate(&mut self, plies: usize) {
+        self.positions.truncate(plies + 1);
+        self.moves.truncate(plies);
+        self.variations.retain(|v| v.ply < plies);
+        self.undone.clear();
+        self.result = self.outcome();
+    }
+
+    // Like `truncate`, but the moves after the first `plies` are kept as a variation.
+    pub fn branch(&mut self, plies: usize) {
+        if plies >= self.moves.len() {return}
+
+        let (later, earlier) = std::mem::take(&mut self.variations).into_iter().partition(|v| v.ply > plies);
+        let moves = self.moves.split_off(plies);
+
+        self.variations = earlier;
+        self.variations.push(Variation { ply: plies, moves, variations: later });
+        self.positions.truncate(plies + 1);
+        self.undone.clear();
+        self.result = self.outcome();
+    }
+
+    // Takes back the last move. The position before it carries the castling rights, en passant
+    // square and clocks, and the positions left are the repetition history.
+    pub fn undo(&mut self) -> bool {
+        let Some(record) = self.moves.pop() else {return false};
+        self.positions.pop();
+        self.undone.push(record);
+        self.result = self.outcome();
+        true
+    }
+
+    // Plays the last move taken back again.
+    pub fn redo(&mut self) -> bool {
+        let Some(record) = self.undone.pop() else {return false};
+        let next = self.position().make(record.m);
+        self.positions.push(next);
+        self.moves.push(record);
+        self.result = self.outcome();
+        true
+    }
+
+    // Swaps the main line for the variation at `path`, an index into `variations` followed by one
+    // into the variations of each line it is nested in; its moves then continue the game.
+    pub fn promote(&mut self, path: &[usize]) {
+        let Some((&index, nested)) = path.split_first() else {return};
+        let v = self.variations.remove(index);
+        let inner = v.variations.len();
+        self.branch(v.ply);
+
+        for record in v.moves {
+            let next = self.position().make(record.m);
+            self.positions.push(next);
+            self.moves.push(record);
+        }
+        self.variations.extend(v.variations);
+        self.result = self.outcome();
+
+        // The lines nested in the one promoted now branch off the main line, last in `variations`.
+        if let Some((&next, rest)) = nested.split_first() {
+            let path: Vec<usize> = [self.variations.len() - inner + next].into_iter().chain(rest.iter().copied()).collect();
+            self.promote(&path);
+        }
+    }
+
+    // The variation at `path`, as taken by `promote`.
+    pub fn variation(&self, path: &[usize]) -> &Variation {
+        let mut v = &self.variations[path[0]];
+        for &i in &path[1..] {v = &v.variations[i]}
+        v
+    }
+
+    // How long each of `white`'s moves took.
+    pub fn spent(&self, white: bool) -> Vec<Duration> {
+        self.moves.iter().zip(&self.positions).filter(|(_, p)| p.white == white).map(|(r, _)| r.elapsed).collect()
+    }
+
+    // Ends the game on time when `white` has run out: a loss, or a draw when the opponent has
+    // too little material to ever mate.
+    pub fn flag(&mut self, white: bool) {
+        let result = if !self.position().mating_material(!white) {"1/2-1/2"} else if white {"0-1"} else {"1-0"};
+        self.result = Some(result.to_string());
+    }
+
+    // Plays a legal move.
+    pub fn push(&mut self, m: Move, elapsed: Duration, eval: Option<f32>) {
+        let pos = *self.position();
+        self.moves.push(Record::new(&pos, m, elapsed, eval));
+        self.positions.push(pos.make(m));
+        self.undone.clear();
+        self.result = self.outcome();
+    }
+
+    fn outcome(&self) -> Option<String> {
+        let pos = self.position();
+
+        if pos.legal_moves().is_empty() {
+            if !pos.in_check(pos.white) {return Some("1/2-1/2".to_string())}
+            return Some(if pos.white {"0-1"} else {"1-0"}.to_string());
+        }
+
+        let hash = pos.hash();
+        let repetitions = self.positions.iter().filter(|p| p.hash() == hash).count();
+
+        if pos.halfmove >= 100 || pos.insufficient_material() || repetitions >= 3 {
+            return Some("1/2-1/2".to_string());
+        }
+
+        None
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let result = self.result.as_deref().unwrap_or("*");
+        let start = self.positions[0];
+
+        let mut pgn = String::new();
+        for (name, value) in [("Event", &self.event), ("Site", &self.site), ("Date", &self.date), ("Round", &self.round), ("White", &self.white), ("Black", &self.black)] {
+            pgn += &format!("[{} \"{}\"]\n", name, value);
+        }
+        pgn += &format!("[Result \"{}\"]\n", result);
+        for (name, value) in self.time_control.iter().flat_map(TimeControl::tags) {
+            pgn += &format!("[{} \"{}\"]\n", name, value);
+        }
+
+        if fen::to_fen(&start) != fen::START {
+            pgn += &format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", fen::to_fen(&start));
+        }
+        pgn.push('\n');
+
+        let mut tokens = movetext(start, 0, &self.moves, &self.variations);
+        tokens.push(result.to_string());
+
+        // Movetext lines are kept under 80 characters.
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + token.len() + 1 > 79 {
+                pgn += &line;
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {line.push(' ')}
+            line += &token;
+        }
+
+        pgn + &line + "\n\n"
+    }
+
+    // Writes the game into `dir` under a name made of the date, time and players.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, String> {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let name = format!("{}-{:02}{:02}{:02}-{}-vs-{}.pgn", self.date.replace('.', ""), secs / 3600 % 24, secs / 60 % 60, secs % 60, self.white, self.black);
+        let path = dir.join(name.replace(' ', "_"));
+
+        std::fs::write(&path, self.to_pgn()).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(path)
+    }
+}
+
+fn replay(pos: &Position, ply: &pgn::Ply) -> Result<Move, String> {
+    san::parse(pos, &ply.san).map_err(|e| format!("{}:{}: {}", ply.line, ply.col, e))
+}
+
+// The records of `plies` played from `pos`, which comes after `first` plies of the game, and
+// the variations that branch off them.
+fn line(mut pos: Position, first: usize, plies: &[pgn::Ply]) -> Result<(Vec<Record>, Vec<Variation>), String> {
+    let mut records = Vec::new();
+    let mut variations = Vec::new();
+
+    for (i, ply) in plies.iter().enumerate() {
+        for v in &ply.variations {
+            let (moves, nested) = line(pos, first + i, v)?;
+            variations.push(Variation { ply: first + i, moves, variations: nested });
+        }
+
+        let comment = ply.comment.as_deref().unwrap_or("");
+        let elapsed = command(comment, "emt").and_then(parse_duration).unwrap_or_default();
+        let eval = command(comment, "eval").and_then(|e| e.parse().ok());
+
+        let m = replay(&pos, ply)?;
+        let mut record = Record::new(&pos, m, elapsed, eval);
+        record.nags = ply.nags.clone();
+        record.comment = Some(strip_commands(comment)).filter(|c| !c.is_empty());
+
+        records.push(record);
+        pos = pos.make(m);
+    }
+
+    Ok((records, variations))
+}
+
+// PGN tokens for `moves` played from `pos` (after `first` plies), with their variations in
+// parentheses after the move they replace.
+fn movetext(mut pos: Position, first: usize, moves: &[Record], variations: &[Variation]) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut numbered = false;
+
+    for (i, record) in moves.iter().enumerate() {
+        // Black's moves are numbered only where a comment, a variation or the start of the line comes first.
+        let nags: String = record.nags.iter().map(|n| format!(" ${}", n)).collect();
+        let number = if pos.white {format!("{}. ", pos.fullmove)} else if !numbered {format!("{}... ", pos.fullmove)} else {String::new()};
+        tokens.push(format!("{}{}{}", number, record.san, nags));
+        numbered = true;
+
+        let secs = record.elapsed.as_secs();
+        let mut comment = Vec::new();
+        if !record.elapsed.is_zero() {comment.push(format!("[%emt {}:{:02}:{:02}]", secs / 3600, secs / 60 % 60, secs % 60))}
+        if let Some(eval) = record.eval {comment.push(format!("[%eval {:.2}]", eval))}
+        if let Some(text) = &record.comment {comment.push(text.clone())}
+
+        if !comment.is_empty() {
+            tokens.push(format!("{{{}}}", comment.join(" ")));
+            numbered = false;
+        }
+
+        for v in variations.iter().filter(|v| v.ply == first + i) {
+            let mut sub = movetext(pos, first + i, &v.moves, &v.variations);
+            if sub.is_empty() {continue}
+
+            sub[0].insert(0, '(');
+            sub.last_mut().unwrap().push(')');
+            tokens.extend(sub);
+            numbered = false;
+        }
+
+        pos = pos.make(record.m);
+    }
+
+    tokens
+}
+
+// The argument of an embedded command such as `[%emt 0:01:05]`.
+fn command<'a>(comment: &'a str, name: &str) -> Option<&'a str> {
+    let start = comment.find(&format!("[%{} ", name))? + name.len() + 3;
+    let len = comment[start..].find(']')?;
+    Some(comment[start..start + len].trim())
+}
+
+fn strip_commands(comment: &str) -> String {
+    let mut text = comment.to_string();
+    while let Some(start) = text.find("[%") {
+        let Some(len) = text[start..].find(']') else {break};
+        text.replace_range(start..start + len + 1, "");
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// `h:mm:ss`, with optional fractions of a second.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let mut secs = 0.;
+    for part in s.split(':') {
+        secs = secs * 60. + part.parse::<f64>().ok()?;
+    }
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+// Current UTC date as YYYY.MM.DD.
+fn today() -> String {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() / 86400) as i64;
+
+    // Civil-from-days conversion on the proleptic Gregorian calendar.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 {mp + 3} else {mp - 9};
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!("{}.{:02}.{:02}", year, month, day)
+}
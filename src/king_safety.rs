@@ -0,0 +1,95 @@
+use crate::params::EvalParams;
+use crate::trace::{Term, Trace};
+use crate::{attacks, Kind, Piece};
+
+pub fn evaluate(board: &[[Option<Piece>; 8]; 8], params: &EvalParams, trace: &mut Trace) {
+    for y in 0..8 {
+        for x in 0..8 {
+            if let Some(piece) = board[y][x] {
+                if piece.kind == Kind::King {
+                    let s = safety(board, params, x, y, piece.white);
+                    trace.add_at(Term::KingSafety, piece.white, s, 0., x, y);
+                }
+            }
+        }
+    }
+}
+
+pub fn safety(board: &[[Option<Piece>; 8]; 8], params: &EvalParams, kx: usize, ky: usize, white: bool) -> f32 {
+    let fwd: isize = if white {-1} else {1};
+
+    let mut score = 0.;
+    let mut danger = 0.;
+
+    for fx in kx.saturating_sub(1)..=(kx + 1).min(7) {
+        let mut own = None;
+        let mut enemy = None;
+        let mut has_own = false;
+        let mut has_enemy = false;
+
+        for (y, row) in board.iter().enumerate() {
+            let Some(p) = row[fx] else {continue};
+            if p.kind != Kind::Pawn {continue}
+
+            let r = relative(y, white);
+
+            if p.white == white {
+                has_own = true;
+                if r > relative(ky, white) && own.is_none_or(|o| r < o) {own = Some(r)}
+            } else {
+                has_enemy = true;
+                if enemy.is_none_or(|e| r < e) {enemy = Some(r)}
+            }
+        }
+
+        match own {
+            Some(r) => score += params.shield[(r - relative(ky, white)).min(7)],
+            None => score += params.shield[0],
+        }
+
+        if let Some(r) = enemy {
+            score += params.storm[r.saturating_sub(relative(ky, white)).min(7)];
+        }
+
+        if !has_own && !has_enemy {danger += params.open_file}
+        else if !has_own {danger += params.semi_open_file}
+    }
+
+    let mut zone = Vec::new();
+    for dy in -1..=2 {
+        for dx in -1..=1 {
+            let (zx, zy) = (kx as isize + dx, ky as isize + dy * fwd);
+            if (0..8).contains(&zx) && (0..8).contains(&zy) && (dy < 2 || dx == 0) {
+                zone.push((zx as usize, zy as usize));
+            }
+        }
+    }
+
+    let mut attackers = 0;
+    let mut weight = 0.;
+
+    for y in 0..8 {
+        for x in 0..8 {
+            let Some(p) = board[y][x] else {continue};
+            if p.white == white || p.kind == Kind::Pawn || p.kind == Kind::King {continue}
+
+            let hits = attacks(board, x, y).iter().filter(|sq| zone.contains(sq)).count() as f32;
+
+            if hits > 0. {
+                attackers += 1;
+                weight += hits * params.attack_weight[p.kind.index()];
+            }
+        }
+    }
+
+    if attackers >= 2 || weight >= params.attack_weight[Kind::Queen.index()] * 2. {
+        danger += weight * attackers.min(4) as f32 / 2.;
+    }
+
+    // Non-linear: a few attackers barely matter, a coordinated attack is decisive.
+    score - f32::min(danger * danger * params.safety_scale, params.safety_cap)
+}
+
+fn relative(y: usize, white: bool) -> usize {
+    if white {7 - y} else {y}
+}
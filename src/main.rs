@@ -1,5 +1,35 @@
 use macroquad::prelude::*;
 
+use clock::TimeControl;
+use params::EvalParams;
+use game::{Game, Variation};
+use position::{Move, Position};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use trace::{Term, Trace};
+
+mod book;
+mod clock;
+mod datagen;
+mod endgame;
+mod fen;
+mod game;
+mod king_safety;
+mod nnue;
+mod params;
+mod pawns;
+mod pgn;
+mod pieces;
+mod position;
+mod san;
+mod search;
+mod syzygy;
+mod trace;
+mod tune;
+mod uci;
+
 #[derive(Copy, Clone, Debug)]
 struct Piece {
     kind: Kind,
@@ -8,111 +38,313 @@ struct Piece {
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 enum Kind {
-    Pawn = 1,
-    Knight = 3,
-    Bishop = 4,
-    Rook = 5,
-    Queen = 9,
-    King = 100,
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+impl Kind {
+    fn index(self) -> usize {
+        match self {
+            Kind::Pawn => 0,
+            Kind::Knight => 1,
+            Kind::Bishop => 2,
+            Kind::Rook => 3,
+            Kind::Queen => 4,
+            Kind::King => 5,
+        }
+    }
 }
 
 impl Piece {
-    fn wp() -> Option<Self> {
+    fn bk() -> Option<Self> {
         Some(Piece {
-            kind: Kind::Pawn,
-            white: true,
+            kind: Kind::King,
+            white: false,
         })
     }
-    fn wn() -> Option<Self> {
-        Some(Piece {
-            kind: Kind::Knight,
-            white: true,
-        })
-    }fn wb() -> Option<Self> {
-        Some(Piece {
-            kind: Kind::Bishop,
-            white: true,
-        })
+}
+
+// GUI options: where finished games are saved, whether moves get eval comments, how long a move
+// takes to slide into place, whether moves can be taken back, and the clock for new games.
+struct Settings {
+    pgn_dir: PathBuf,
+    pgn_evals: bool,
+    animation: Duration,
+    takebacks: bool,
+    time_control: Option<TimeControl>,
+}
+
+// Time controls the new game dialog steps through.
+const TIME_CONTROLS: [&str; 6] = ["60", "180+2", "300d5", "600+5", "1800", "40/5400+30"];
+
+#[derive(Copy, Clone, PartialEq)]
+enum Player {
+    Human,
+    // Search depth in plies, from 1 to MAX_STRENGTH.
+    Engine(u32),
+}
+
+const MAX_STRENGTH: u32 = 8;
+
+impl Player {
+    fn name(self) -> String {
+        match self {
+            Player::Human => "Human".to_string(),
+            Player::Engine(depth) => format!("Engine (depth {})", depth),
+        }
     }
-    fn wr() -> Option<Self> {
-        Some(Piece {
-            kind: Kind::Rook,
-            white: true,
-        })
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    if let Some(i) = args.iter().position(|a| a == "--params") {
+        let Some(path) = args.get(i + 1).cloned() else {
+            eprintln!("--params needs a file");
+            std::process::exit(1);
+        };
+
+        match EvalParams::load(&path) {
+            Ok(p) => params::set(p),
+            Err(e) => {
+                eprintln!("invalid parameter file {}", e);
+                std::process::exit(1);
+            },
+        }
+
+        args.drain(i..i + 2);
     }
-    fn wq() -> Option<Self> {
-        Some(Piece {
-            kind: Kind::Queen,
-            white: true,
-        })
+
+    if let Some(i) = args.iter().position(|a| a == "--net") {
+        let Some(path) = args.get(i + 1).cloned() else {
+            eprintln!("--net needs a file");
+            std::process::exit(1);
+        };
+
+        if let Err(e) = nnue::load(&path) {
+            eprintln!("invalid network {}", e);
+            std::process::exit(1);
+        }
+
+        nnue::set_enabled(true);
+        args.drain(i..i + 2);
     }
-    fn wk() -> Option<Self> {
-        Some(Piece {
-            kind: Kind::King,
-            white: true,
-        })
+
+    if let Some(i) = args.iter().position(|a| a == "--book") {
+        let Some(path) = args.get(i + 1).cloned() else {
+            eprintln!("--book needs a file");
+            std::process::exit(1);
+        };
+
+        if let Err(e) = book::load(&path) {
+            eprintln!("invalid book {}", e);
+            std::process::exit(1);
+        }
+
+        args.drain(i..i + 2);
     }
 
-    fn bp() -> Option<Self> {
-        Some(Piece {
-            kind: Kind::Pawn,
-            white: false,
-        })
+    if let Some(i) = args.iter().position(|a| a == "--book-depth") {
+        let Some(plies) = args.get(i + 1).and_then(|s| s.parse().ok()) else {
+            eprintln!("--book-depth needs a number of plies");
+            std::process::exit(1);
+        };
+
+        book::set_max_depth(plies);
+        args.drain(i..i + 2);
     }
-    fn bn() -> Option<Self> {
-        Some(Piece {
-            kind: Kind::Knight,
-            white: false,
-        })
-    }fn bb() -> Option<Self> {
-        Some(Piece {
-            kind: Kind::Bishop,
-            white: false,
-        })
+
+    if let Some(i) = args.iter().position(|a| a == "--book-best") {
+        book::set_best_only(true);
+        args.remove(i);
     }
-    fn br() -> Option<Self> {
-        Some(Piece {
-            kind: Kind::Rook,
-            white: false,
-        })
+
+    if let Some(i) = args.iter().position(|a| a == "--syzygy") {
+        let Some(dir) = args.get(i + 1).cloned() else {
+            eprintln!("--syzygy needs a directory");
+            std::process::exit(1);
+        };
+
+        match syzygy::init(&dir) {
+            Ok(n) => println!("found {} tablebases (up to {} pieces) in {}", n, syzygy::max_pieces(), dir),
+            Err(e) => {
+                eprintln!("invalid tablebase path {}", e);
+                std::process::exit(1);
+            },
+        }
+
+        args.drain(i..i + 2);
     }
-    fn bq() -> Option<Self> {
-        Some(Piece {
-            kind: Kind::Queen,
-            white: false,
-        })
+
+    let mut settings = Settings { pgn_dir: PathBuf::from("games"), pgn_evals: false, animation: Duration::from_millis(200), takebacks: true, time_control: None };
+
+    if let Some(i) = args.iter().position(|a| a == "--anim-ms") {
+        let Some(ms) = args.get(i + 1).and_then(|s| s.parse().ok()) else {
+            eprintln!("--anim-ms needs a number of milliseconds");
+            std::process::exit(1);
+        };
+
+        settings.animation = Duration::from_millis(ms);
+        args.drain(i..i + 2);
     }
-    fn bk() -> Option<Self> {
-        Some(Piece {
-            kind: Kind::King,
-            white: false,
-        })
+
+    if let Some(i) = args.iter().position(|a| a == "--pgn-dir") {
+        let Some(dir) = args.get(i + 1).cloned() else {
+            eprintln!("--pgn-dir needs a directory");
+            std::process::exit(1);
+        };
+
+        settings.pgn_dir = PathBuf::from(dir);
+        args.drain(i..i + 2);
+    }
+
+    if let Some(i) = args.iter().position(|a| a == "--pgn-evals") {
+        settings.pgn_evals = true;
+        args.remove(i);
+    }
+
+    if let Some(i) = args.iter().position(|a| a == "--time") {
+        let Some(tc) = args.get(i + 1).cloned() else {
+            eprintln!("--time needs a time control such as 300, 180+2, 300d5 or 40/5400+30");
+            std::process::exit(1);
+        };
+
+        match TimeControl::parse(&tc) {
+            Ok(tc) => settings.time_control = Some(tc),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            },
+        }
+        args.drain(i..i + 2);
+    }
+
+    if let Some(i) = args.iter().position(|a| a == "--no-takebacks") {
+        settings.takebacks = false;
+        args.remove(i);
+    }
+
+    let mut players = [Player::Human, Player::Engine(3)];
+    let mut start = Game::new(Position::start(), &players[0].name(), &players[1].name());
+    start.time_control = settings.time_control;
+
+    if let Some(i) = args.iter().position(|a| a == "--pgn") {
+        let Some(path) = args.get(i + 1).cloned() else {
+            eprintln!("--pgn needs a file");
+            std::process::exit(1);
+        };
+        args.drain(i..i + 2);
+
+        let mut index = 1;
+        if let Some(i) = args.iter().position(|a| a == "--game") {
+            let Some(n) = args.get(i + 1).and_then(|s| s.parse().ok()) else {
+                eprintln!("--game needs a game number");
+                std::process::exit(1);
+            };
+
+            index = n;
+            args.drain(i..i + 2);
+        }
+
+        match load_game(&path, index) {
+            Ok(game) => {
+                start = game;
+                players = [Player::Human, Player::Human];
+            },
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            },
+        }
+    }
+
+    if let Some(i) = args.iter().position(|a| a == "--nnue") {
+        nnue::set_enabled(true);
+        args.remove(i);
     }
+
+    match args.first().map(String::as_str) {
+        Some("eval") => {
+            let fen = if args.len() > 1 {args[1..].join(" ")} else {fen::START.to_string()};
+
+            match fen::parse_board(&fen) {
+                Ok(board) => {
+                    print!("{}", evaluate_trace(&board, &params::get(), false).report());
+
+                    if nnue::enabled() {
+                        println!("NNUE  {:.2} (white's point of view)", nnue::evaluate(&board));
+                    }
+                },
+                Err(e) => {
+                    eprintln!("invalid FEN: {}", e);
+                    std::process::exit(1);
+                },
+            }
+        },
+        Some("save-params") => {
+            let Some(path) = args.get(1) else {
+                eprintln!("usage: chess save-params <file>");
+                std::process::exit(1);
+            };
+
+            if let Err(e) = params::get().save(path) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        Some("book") if args.get(1).map(String::as_str) == Some("build") => {
+            if let Err(e) = book::build(&args[2..]) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        Some("datagen") => {
+            if let Err(e) = datagen::run(&args[1..]) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        Some("tune") => {
+            if let Err(e) = tune::run(&args[1..]) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        Some("uci") => uci::run(),
+        _ => {
+            let conf = Conf { window_title: "chess".to_string(), window_width: 1100, window_height: 700, ..Default::default() };
+            macroquad::Window::from_config(conf, game(settings, start, players));
+        },
+    }
+}
+
+// The `index`th game (from 1) of a PGN file, checked move by move.
+fn load_game(path: &str, index: usize) -> Result<Game, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let games = pgn::parse(&text)?;
+
+    let g = games.get(index.wrapping_sub(1)).ok_or(format!("no game {} ({} in file)", index, games.len()))?;
+    Game::from_pgn(g).map_err(|e| format!("game {}: {}", index, e))
 }
 
-const HEAT_MAP: [[f32; 8]; 8] = [
-    [1.0, 1.0, 2.0, 2.5, 2.5, 2.0, 1.0, 1.0],
-    [1.0, 2.0, 3.0, 3.0, 3.0, 3.0, 2.0, 1.0],
-    [2.0, 3.0, 4.0, 3.5, 3.5, 4.0, 3.0, 2.0],
-    [2.5, 3.0, 3.5, 4.0, 4.0, 3.5, 3.0, 2.5],
-    [2.5, 3.0, 3.5, 4.0, 4.0, 3.5, 3.0, 2.5],
-    [2.0, 3.0, 4.0, 3.5, 3.5, 4.0, 3.0, 2.0],
-    [1.0, 2.0, 3.0, 3.0, 3.0, 3.0, 2.0, 1.0],
-    [1.0, 1.0, 2.0, 2.5, 2.5, 2.0, 1.0, 1.0],
-];
-
-#[macroquad::main("chess")]
-async fn main() {
-    let mut board: [[Option<Piece>; 8]; 8] = [
-        [Piece::br(),   Piece::bn(),    Piece::bb(),    Piece::bq(),    Piece::bk(),    Piece::bb(),    Piece::bn(),    Piece::br()],
-        [Piece::bp(),   Piece::bp(),    Piece::bp(),    Piece::bp(),    Piece::bp(),    Piece::bp(),    Piece::bp(),    Piece::bp()],
-        [None,          None,           None,           None,           None,           None,           None,           None],
-        [None,          None,           None,           None,           None,           None,           None,           None],
-        [None,          None,           None,           None,           None,           None,           None,           None],
-        [None,          None,           None,           None,           None,           None,           None,           None],
-        [Piece::wp(),   Piece::wp(),    Piece::wp(),    Piece::wp(),    Piece::wp(),    Piece::wp(),    Piece::wp(),    Piece::wp()],
-        [Piece::wr(),   Piece::wn(),    Piece::wb(),    Piece::wq(),    Piece::wk(),    Piece::wb(),    Piece::wn(),    Piece::wr()],
-    ];
+// `players` are white and black. The board is shown from black's side when only black is human.
+async fn game(settings: Settings, mut game: Game, mut players: [Player; 2]) {
+    let mut flipped = false;
+    let mut dialog: Option<([Player; 2], Option<TimeControl>)> = None;
+
+    let mut moved = Instant::now();
+    let mut saved = game.result.is_some();
+    let mut status = String::new();
+
+    // The ply being shown: moves after it can be stepped through, and moving from it replaces them.
+    let mut view = game.moves.len();
+    let mut analysing = false;
+    let mut analysis: Option<Analysis> = None;
+    let mut thinking: Option<Thinking> = None;
 
     let pawn = Texture2D::from_file_with_format(include_bytes!("../textures/pawn.jpg"), None);
     let knight = Texture2D::from_file_with_format(include_bytes!("../textures/knight.jpg"), None);
@@ -122,91 +354,309 @@ async fn main() {
     let king = Texture2D::from_file_with_format(include_bytes!("../textures/king.jpg"), None);
 
     let mut selected = (0, 0);
-    let mut promotion: Option<(usize, usize)> = None;
+    let mut promotion: Option<Move> = None;
+
+    // A move made from an earlier position, waiting for the choice between dropping the later
+    // moves and keeping them as a variation.
+    let mut pending: Option<(Move, bool)> = None;
+
+    // The square whose piece follows the cursor, and the ply whose move is sliding into place.
+    let mut drag: Option<(usize, usize)> = None;
+    let mut animation: Option<(usize, Instant)> = None;
+
+    let mut show_eval = false;
+    let mut show_heatmap = false;
+    let mut editor: Option<Editor> = None;
 
-    let mut wmk = false;
-    let mut bmk = false;
+    let texture = |kind| match kind {
+        Kind::Pawn => pawn,
+        Kind::Knight => knight,
+        Kind::Bishop => bishop,
+        Kind::Rook => rook,
+        Kind::Queen => queen,
+        Kind::King => king,
+    };
 
     loop {
         next_frame().await;
         clear_background(Color::new(0.3, 0.15, 0.1, 1.));
 
+        if is_key_pressed(KeyCode::E) {show_eval = !show_eval}
+        if is_key_pressed(KeyCode::H) {show_heatmap = !show_heatmap}
+        if is_key_pressed(KeyCode::N) {nnue::set_enabled(!nnue::enabled())}
+
         let oy = screen_height() / 10.;
         let ox = screen_width() / 2. - oy*4.;
 
+        // The editor takes over the window until a position is played from or it is cancelled.
+        if let Some(e) = &mut editor {
+            match setup_editor(e, &texture, flipped, ox, oy) {
+                Some(Setup::Cancel) => editor = None,
+                Some(setup) => {
+                    let analyse = matches!(setup, Setup::Analyse);
+                    if analyse {players = [Player::Human, Player::Human]}
+                    analysing |= analyse;
+
+                    let time_control = game.time_control.filter(|_| !analyse);
+                    game = Game::new(e.pos, &players[0].name(), &players[1].name());
+                    game.time_control = time_control;
+
+                    (view, saved, selected, drag, animation, thinking) = (0, false, (0, 0), None, None, None);
+                    moved = Instant::now();
+                    editor = None;
+                },
+                None => {},
+            }
+            continue;
+        }
+
+        let mut bx = ox;
+        let mut nav = |label: &str, width: f32| {
+            bx += width * oy + oy * 0.1;
+            button(label, bx - width * oy - oy * 0.1, oy * 9.1, width * oy, oy * 0.45)
+        };
+
+        if (nav("|<", 0.6) || is_key_pressed(KeyCode::Home)) && promotion.is_none() && pending.is_none() {view = 0}
+        if (nav("<", 0.6) || is_key_pressed(KeyCode::Left)) && promotion.is_none() && pending.is_none() {view = view.saturating_sub(1)}
+        if (nav(">", 0.6) || is_key_pressed(KeyCode::Right)) && promotion.is_none() && pending.is_none() {view = (view + 1).min(game.moves.len())}
+        if (nav(">|", 0.6) || is_key_pressed(KeyCode::End)) && promotion.is_none() && pending.is_none() {view = game.moves.len()}
+
+        // Hands the side to move to the human and the other one to the engine.
+        if nav("Play", 0.9) && promotion.is_none() && pending.is_none() && (settings.takebacks || view == game.moves.len()) {
+            let white = game.positions[view].white;
+            let engine = players.iter().find(|p| **p != Player::Human).copied().unwrap_or(Player::Engine(3));
+            players = if white {[Player::Human, engine]} else {[engine, Player::Human]};
+            flipped = !white;
+
+            game.truncate(view);
+            saved = false;
+            view = game.moves.len();
+            moved = Instant::now();
+        }
+
+        // Undo and Redo step a whole move pair, Ctrl+Z and Ctrl+Y a single ply (a pair with Shift).
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let plies = if is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) {2} else {1};
+        let undo = if nav("Undo", 0.9) {2} else if ctrl && is_key_pressed(KeyCode::Z) {plies} else {0};
+        let redo = if nav("Redo", 0.9) {2} else if ctrl && is_key_pressed(KeyCode::Y) {plies} else {0};
+
+        if undo + redo > 0 && promotion.is_none() && pending.is_none() && dialog.is_none() {
+            if !settings.takebacks {
+                status = "Takebacks are disabled".to_string();
+            } else {
+                for _ in 0..undo {game.undo();}
+                for _ in 0..redo {game.redo();}
+                view = game.moves.len();
+                (saved, selected, drag, animation) = (false, (0, 0), None, None);
+                moved = Instant::now();
+
+                let waiting = !game.undone.is_empty() && players[!game.position().white as usize] != Player::Human;
+                status = if waiting {"Engine paused: Redo, or Play to take its side".to_string()} else {String::new()};
+            }
+        }
+
+        // The side to move's clock runs from the last move, and stops while moves can be redone.
+        let white = game.position().white;
+        let running = if game.result.is_none() && game.undone.is_empty() {moved.elapsed()} else {Duration::ZERO};
+        let left = game.time_control.map(|tc| tc.left(&game.spent(white), running));
+
+        if left.is_some_and(|t| t.is_zero()) {
+            game.flag(white);
+            saved = false;
+        }
+
+        // The engine waits a moment after each move so that engine games can be followed, and
+        // does not play while there are moves taken back that could be replayed. It thinks on a
+        // thread of its own, given up as soon as the game leaves the position it is thinking on.
+        let ready = dialog.is_none() && view == game.moves.len() && game.undone.is_empty() && game.result.is_none();
+        let depth = match players[!white as usize] {Player::Engine(depth) if ready => Some(depth), _ => None};
+        let history: Vec<u64> = game.positions.iter().map(Position::hash).collect();
+
+        if thinking.as_ref().is_some_and(|t| Some(t.depth) != depth || t.history != history) {thinking = None}
+
+        if let Some(depth) = depth.filter(|_| thinking.is_none() && moved.elapsed() > settings.animation.max(Duration::from_millis(300))) {
+            let budget = game.time_control.zip(left).map(|(tc, left)| tc.budget(left, game.spent(white).len()));
+            thinking = Some(Thinking::start(*game.position(), history, depth, budget));
+        }
+
+        if let Some(chosen) = thinking.as_ref().and_then(Thinking::chosen) {
+            if let Some((m, eval)) = chosen {
+                game.push(m, moved.elapsed(), eval.filter(|_| settings.pgn_evals));
+            }
+            view = game.moves.len();
+            moved = Instant::now();
+            animation = Some((view, moved));
+            thinking = None;
+        }
+
+        // Click the piece and then its target, or drag it there; drops on illegal squares snap back.
+        let hovered = square_at(mouse_position(), ox, oy, flipped);
+        let current = game.positions[view];
+        let legal = current.legal_moves();
+        let human = players[!current.white as usize] == Player::Human && if view < game.moves.len() {settings.takebacks} else {game.result.is_none()};
+        let mut attempt: Option<(Move, bool)> = None;
+        let picking = promotion.is_some();
+        let find = |from: (usize, usize), to: (usize, usize)| legal.iter().copied().find(|m| (m.0, m.1, m.2, m.3) == (from.0, from.1, to.0, to.1));
+
+        if promotion.is_none() && dialog.is_none() && pending.is_none() {
+            if let Some(sq) = hovered.filter(|_| is_mouse_button_pressed(MouseButton::Left)) {
+                if let Some(m) = find(selected, sq).filter(|_| human) {
+                    attempt = Some((m, true));
+                } else {
+                    selected = sq;
+                    if human && current.board[sq.1][sq.0].is_some_and(|p| p.white == current.white) {drag = Some(sq)}
+                }
+            }
+
+            if is_mouse_button_released(MouseButton::Left) {
+                if let Some(from) = drag.take() {
+                    if let Some(m) = hovered.and_then(|sq| find(from, sq)) {attempt = Some((m, false))}
+                }
+            }
+        }
+
+        if let Some((m, _)) = attempt {
+            selected = (m.2, m.3);
+            if view < game.moves.len() {pending = attempt.take()}
+        }
+
+        if let Some((m, animate)) = attempt {
+            if current.is_promotion(m) {
+                promotion = Some(m);
+            } else {
+                saved = false;
+                game.push(m, moved.elapsed(), None);
+                view = game.moves.len();
+                moved = Instant::now();
+                if animate {animation = Some((view, moved))}
+            }
+        }
+
+        let pos = game.positions[view];
+
+        if nav(if analysing {"Stop"} else {"Analyse"}, 1.3) || is_key_pressed(KeyCode::A) {analysing = !analysing}
+
+        // The search starts over whenever the position shown, or the moves leading to it, change.
+        let history: Vec<u64> = game.positions[..=view].iter().map(Position::hash).collect();
+        if !analysing {
+            analysis = None;
+        } else if analysis.as_ref().is_none_or(|a| a.history != history) {
+            analysis = Some(Analysis::start(pos, history));
+        }
+        let board = pos.board;
+
+        let trace = evaluate_trace(&board, &params::get(), show_heatmap);
+
         draw_rectangle(ox, oy, oy*8., oy*8., Color::new(1.0, 0.8, 0.6, 1.));
 
-        for y in 0..8 {
-            for x in 0..8 {
+        let last = view.checked_sub(1).map(|i| game.moves[i].m);
+        let check = pos.king(pos.white).filter(|_| pos.in_check(pos.white));
+        let slides = match animation {
+            Some((ply, start)) if ply == view && start.elapsed() < settings.animation => {
+                let t = start.elapsed().as_secs_f32() / settings.animation.as_secs_f32();
+                slides(&game.positions[ply - 1], game.moves[ply - 1].m).into_iter().map(|(from, to)| (from, to, t)).collect()
+            },
+            _ => Vec::new(),
+        };
+        let screen = move |(x, y): (usize, usize)| if flipped {(ox + (7 - x) as f32 * oy, oy + (7 - y) as f32 * oy)} else {(ox + x as f32 * oy, oy + y as f32 * oy)};
+
+        let targets: Vec<Move> = match board[selected.1][selected.0] {
+            Some(p) if p.white == pos.white => pos.legal_moves().into_iter().filter(|m| (m.0, m.1) == selected).collect(),
+            _ => Vec::new(),
+        };
+
+        for (y, row) in board.iter().enumerate() {
+            for (x, &square) in row.iter().enumerate() {
+                let (cx, cy) = if flipped {(7 - x, 7 - y)} else {(x, y)};
+                let (sx, sy) = screen((x, y));
+
                 if (x%2!=0&&y%2!=0) || (x%2==0&&y%2==0) {
-                    draw_rectangle(ox + x as f32 * oy, oy + y as f32 * oy, oy, oy, Color::new(0.15, 0.4, 0.15, 1.));
+                    draw_rectangle(sx, sy, oy, oy, Color::new(0.15, 0.4, 0.15, 1.));
                 }
 
-                if let Some(piece) = board[y][x] {
-                    let texture = match piece.kind {
-                        Kind::Pawn => pawn,
-                        Kind::Knight => knight,
-                        Kind::Bishop => bishop,
-                        Kind::Rook => rook,
-                        Kind::Queen => queen,
-                        Kind::King => king,
-                    };
-                    let mut color = Color::new(0.25, 0.25, 0.25, 1.);
-                    if piece.white {color = WHITE}
-                    draw_texture_ex(
-                        texture, 
-                        ox + x as f32 * oy, 
-                        oy + y as f32 * oy, 
-                        color,
-                        DrawTextureParams {
-                            dest_size: Some(Vec2::new(oy, oy)),
-                            ..Default::default()
-                        }
-                    );
+                if last.is_some_and(|m| (m.0, m.1) == (x, y) || (m.2, m.3) == (x, y)) {
+                    draw_rectangle(sx, sy, oy, oy, Color::new(0.9, 0.9, 0.2, 0.35));
+                }
+
+                if check == Some((x, y)) {
+                    for i in 0..4 {
+                        draw_circle(sx + oy / 2., sy + oy / 2., oy * (0.5 - i as f32 * 0.1), Color::new(1., 0., 0., 0.25));
+                    }
                 }
-                if mouse_position().0 > ox + x as f32 * oy && 
-                    mouse_position().0 < ox + x as f32 * oy + oy && 
-                    mouse_position().1 > oy + y as f32 * oy && 
-                    mouse_position().1 < oy + y as f32 * oy + oy && 
-                    is_mouse_button_pressed(MouseButton::Left) && 
-                    promotion.is_none() {
 
-                    if valid_moves(board, wmk, bmk).contains(&(selected.0, selected.1, x, y)) && board[selected.1][selected.0].unwrap().white {
-                        board[y][x] = board[selected.1][selected.0];
-                        board[selected.1][selected.0] = None;
+                if show_heatmap && square.is_some() {
+                    let v = trace.square(x, y);
+                    let color = if v > 0. {Color::new(0.2, 0.9, 0.2, f32::min(v.abs() / 3., 0.7))} else {Color::new(0.9, 0.2, 0.2, f32::min(v.abs() / 3., 0.7))};
 
-                        if let Some(piece) = board[y][x] {
-                            if piece.kind == Kind::Pawn && y == 0 {
-                                promotion = Some((x, y));
-                                board[y][x] = None;
-                            }
+                    draw_rectangle(sx, sy, oy, oy, color);
+                }
 
-                            if !wmk && piece.kind == Kind::King {
-                                if x == 6 && y == 7 && selected.0 == 4 {
-                                    board[7][7] = None;
-                                    board[7][5] = Piece::wr();
-                                } else if x == 2 && y == 7 && selected.0 == 4 {
-                                    board[7][0] = None;
-                                    board[7][3] = Piece::wr();
-                                }
+                let moving = drag == Some((x, y)) || slides.iter().any(|&(_, to, _)| to == (x, y));
 
-                                wmk = true;
-                            }
-                        }
+                if let Some(piece) = square.filter(|_| !moving) {
+                    draw_piece(texture(piece.kind), piece.white, sx, sy, oy);
 
-                        play(&mut board, wmk, &mut bmk);
+                    if show_heatmap {
+                        draw_text(&format!("{:.1}", trace.square(x, y)), sx + oy * 0.05, sy + oy * 0.95, oy * 0.3, YELLOW);
                     }
-
-                    selected = (x, y);
                 }
+                if let Some(&m) = targets.iter().find(|m| (m.2, m.3) == (x, y)) {
+                    let shade = Color::new(0., 0., 0., 0.3);
+                    if pos.is_capture(m) {
+                        draw_circle_lines(sx + oy / 2., sy + oy / 2., oy * 0.45, oy * 0.07, shade);
+                    } else {
+                        draw_circle(sx + oy / 2., sy + oy / 2., oy * 0.15, shade);
+                    }
+                }
+
                 if (x, y) == selected {
-                    draw_rectangle_lines(ox + x as f32 * oy, oy + y as f32 * oy, oy, oy, oy/8., Color::new(0.3, 0.5, 0.8, 1.));
-                    draw_rectangle_lines(ox + x as f32 * oy, oy + y as f32 * oy, oy, oy, oy/16., Color::new(0.4, 0.7, 1., 1.));
+                    draw_rectangle_lines(sx, sy, oy, oy, oy/8., Color::new(0.3, 0.5, 0.8, 1.));
+                    draw_rectangle_lines(sx, sy, oy, oy, oy/16., Color::new(0.4, 0.7, 1., 1.));
                 } 
+
+                // Files along the bottom edge and ranks along the left one, whichever way up the board is.
+                let label = if (x + y) % 2 == 0 {Color::new(1.0, 0.8, 0.6, 1.)} else {Color::new(0.15, 0.4, 0.15, 1.)};
+                if cy == 7 {
+                    draw_text(&((b'a' + x as u8) as char).to_string(), sx + oy * 0.8, sy + oy * 0.95, oy * 0.25, label);
+                }
+                if cx == 0 {
+                    draw_text(&(8 - y).to_string(), sx + oy * 0.05, sy + oy * 0.2, oy * 0.25, label);
+                }
+            }
+        }
+
+        for &(from, to, t) in &slides {
+            let ((fx, fy), (tx, ty)) = (screen(from), screen(to));
+            if let Some(piece) = board[to.1][to.0] {
+                draw_piece(texture(piece.kind), piece.white, fx + (tx - fx) * t, fy + (ty - fy) * t, oy);
+            }
+        }
+
+        if let Some(piece) = drag.and_then(|(x, y)| board[y][x]) {
+            let (mx, my) = mouse_position();
+            draw_piece(texture(piece.kind), piece.white, mx - oy / 2., my - oy / 2., oy);
+        }
+
+        // The clocks sit level with the player at each edge of the board.
+        if let Some(tc) = game.time_control {
+            for side in [true, false] {
+                let y = if side == flipped {oy} else {oy * 8.2};
+                let t = if side == white {left.unwrap_or_default()} else {tc.left(&game.spent(side), Duration::ZERO)};
+                let ticking = side == white && !running.is_zero();
+
+                draw_rectangle(ox - oy * 3.2, y, oy * 2.3, oy * 0.8, if ticking {Color::new(0.9, 0.9, 0.9, 1.)} else {Color::new(0.15, 0.15, 0.15, 1.)});
+                let color = if t < Duration::from_secs(10) {RED} else if ticking {BLACK} else {WHITE};
+                draw_text(&clock::format(t), ox - oy * 3.05, y + oy * 0.6, oy * 0.5, color);
             }
+            draw_text(&tc.to_string(), ox - oy * 3.2, oy * 4.6, oy * 0.3, LIGHTGRAY);
         }
 
-        let eval = evaluate(board);
+        let mut eval = endgame::evaluate(&board, pos.white, || if nnue::enabled() {nnue::evaluate(&board)} else {trace.total()});
+        if let Some((r, _)) = analysis.as_ref().and_then(Analysis::latest) {
+            eval = pawns(r.score, pos.white).clamp(-200., 200.);
+            // The analysis of the position a move leads to stands as that move's evaluation.
+            if settings.pgn_evals && view > 0 {game.moves[view - 1].eval = Some(pawns(r.score, pos.white))}
+        }
 
         draw_rectangle(ox - oy * 0.7, oy, oy * 0.3, oy * 8., Color::new(0.15, 0.15, 0.15, 1.));
 
@@ -214,234 +664,649 @@ async fn main() {
 
         draw_rectangle_lines(ox - oy * 0.7, oy, oy * 0.3, oy * 8., oy * 0.1, BLACK);
 
-        if let Some((x, y)) = promotion {
-            draw_texture_ex(
-                queen, 
-                ox + 2. * oy, 
-                0., 
-                WHITE,
-                DrawTextureParams {
-                    dest_size: Some(Vec2::new(oy, oy)),
-                    ..Default::default()
-                }
-            );
-
-            draw_texture_ex(
-                rook, 
-                ox + 3. * oy, 
-                0., 
-                WHITE,
-                DrawTextureParams {
-                    dest_size: Some(Vec2::new(oy, oy)),
-                    ..Default::default()
-                }
-            );
-
-            draw_texture_ex(
-                bishop, 
-                ox + 4. * oy, 
-                0., 
-                WHITE,
-                DrawTextureParams {
-                    dest_size: Some(Vec2::new(oy, oy)),
-                    ..Default::default()
-                }
-            );
-
-            draw_texture_ex(
-                knight, 
-                ox + 5. * oy, 
-                0., 
-                WHITE,
-                DrawTextureParams {
-                    dest_size: Some(Vec2::new(oy, oy)),
-                    ..Default::default()
-                }
-            );
+        if nnue::enabled() {
+            draw_text("NNUE", ox - oy * 0.85, oy * 0.8, oy * 0.3, WHITE);
+        }
+
+        let (bx, by) = (ox + oy * 8.3, oy);
+        let clicked = button("Save PGN", bx, by, oy * 1.6, oy * 0.5);
 
-            if is_mouse_button_pressed(MouseButton::Left) && mouse_position().0 > ox + 2. * oy && mouse_position().0 < ox + 6. * oy && mouse_position().1 < oy {
-                if mouse_position().0 < ox + 3. * oy {board[y][x] = Piece::wq()}
-                else if mouse_position().0 < ox + 4. * oy {board[y][x] = Piece::wr()}
-                else if mouse_position().0 < ox + 5. * oy {board[y][x] = Piece::wb()}
-                else {board[y][x] = Piece::wn()}
+        if button("New game", bx, by + oy * 0.6, oy * 1.6, oy * 0.5) && promotion.is_none() {dialog = Some((players, game.time_control))}
+        if button("Flip", bx, by + oy * 1.2, oy * 1.6, oy * 0.5) || is_key_pressed(KeyCode::F) {flipped = !flipped}
+
+        if button("Set up", bx, by + oy * 1.8, oy * 1.6, oy * 0.5) && promotion.is_none() && pending.is_none() && dialog.is_none() {
+            editor = Some(Editor { pos: game.positions[view], brush: Some(Piece { kind: Kind::Pawn, white: true }), error: String::new() });
+        }
 
+        if let Some(result) = &game.result {
+            draw_text(&format!("Result {}", result), bx, by + oy * 2.7, oy * 0.35, WHITE);
+        }
+
+        let jump = draw_move_list(&game, view, bx, by + oy * 3., oy * 3.4, oy * 5., oy);
+        if promotion.is_none() && dialog.is_none() && pending.is_none() && (settings.takebacks || matches!(jump, Some(Jump::Ply(_)))) {
+            match jump {
+                Some(Jump::Ply(ply)) => view = ply,
+                Some(Jump::Variation(path, n)) => {
+                    view = game.variation(&path).ply + n + 1;
+                    game.promote(&path);
+                    saved = false;
+                },
+                None => {},
+            }
+        }
+
+        if let Some(comment) = view.checked_sub(1).and_then(|i| game.moves[i].comment.as_ref()) {
+            draw_text(comment, ox, oy * 0.35, oy * 0.25, LIGHTGRAY);
+        }
+
+        if let Some(a) = &analysis {
+            let (info, line) = a.summary(screen_width() - ox - oy * 0.2, oy * 0.25);
+            draw_text(&info, ox, oy * 0.62, oy * 0.25, YELLOW);
+            draw_text(&line, ox, oy * 0.9, oy * 0.25, YELLOW);
+        }
+
+        if clicked || (game.result.is_some() && !saved) {
+            saved = game.result.is_some();
+            status = match game.save(&settings.pgn_dir) {
+                Ok(path) => format!("saved {}", path.display()),
+                Err(e) => e,
+            };
+        }
+        draw_text(&status, ox, oy * 9.5, oy * 0.25, WHITE);
+
+        if show_eval {
+            draw_eval_panel(&trace, ox, oy);
+        }
+
+        if let Some((choice, control)) = &mut dialog {
+            match new_game_dialog(choice, control, ox, oy) {
+                Some(true) => {
+                    players = *choice;
+                    flipped = players[0] != Player::Human && players[1] == Player::Human;
+                    game = Game::new(Position::start(), &players[0].name(), &players[1].name());
+                    game.time_control = *control;
+
+                    (view, saved, selected, analysis, thinking) = (0, false, (0, 0), None, None);
+                    moved = Instant::now();
+                    dialog = None;
+                },
+                Some(false) => dialog = None,
+                None => {},
+            }
+        }
+
+        if let Some((m, animate)) = pending {
+            match choice_dialog("Moves follow this position", &["Replace them", "Keep as variation", "Cancel"], ox, oy) {
+                Some(2) => pending = None,
+                Some(keep) => {
+                    if keep == 1 {game.branch(view)} else {game.truncate(view)}
+                    saved = false;
+                    pending = None;
+
+                    if pos.is_promotion(m) {
+                        promotion = Some(m);
+                    } else {
+                        game.push(m, moved.elapsed(), None);
+                        view = game.moves.len();
+                        moved = Instant::now();
+                        if animate {animation = Some((view, moved))}
+                    }
+                },
+                None => {},
+            }
+        }
+
+        // The choices run from the promotion square towards the middle of the board; clicking
+        // anywhere else takes the move back.
+        if let Some(m) = promotion {
+            let (px, py) = screen((m.2, m.3));
+            let step = if py < oy * 5. {oy} else {-oy};
+            let kinds = [Kind::Queen, Kind::Knight, Kind::Rook, Kind::Bishop];
+
+            draw_rectangle(px, py.min(py + step * 3.), oy, oy * 4., Color::new(0.5, 0.5, 0.5, 0.95));
+            for (i, &kind) in kinds.iter().enumerate() {
+                draw_piece(texture(kind), pos.white, px, py + step * i as f32, oy);
+            }
+
+            if picking && is_mouse_button_pressed(MouseButton::Left) {
+                let (mx, my) = mouse_position();
+                let choice = kinds.iter().enumerate()
+                    .find(|&(i, _)| mx >= px && mx < px + oy && my >= py + step * i as f32 && my < py + step * i as f32 + oy)
+                    .map(|(_, &kind)| kind);
+
+                if let Some(kind) = choice {
+                    saved = false;
+                    game.push((m.0, m.1, m.2, m.3, Some(kind)), moved.elapsed(), None);
+                    view = game.moves.len();
+                    moved = Instant::now();
+                }
                 promotion = None;
             }
         }
     }
 }
 
-fn play(board: &mut [[Option<Piece>; 8]; 8], wmk: bool, bmk: &mut bool) {
-    let vm = valid_moves(*board, wmk, *bmk);
+// Lets each side be picked as human or engine, with the engine's search depth, and the clock.
+// Returns whether the game should start once Start or Cancel is pressed.
+fn new_game_dialog(players: &mut [Player; 2], control: &mut Option<TimeControl>, ox: f32, oy: f32) -> Option<bool> {
+    draw_rectangle(ox + oy * 1.5, oy * 3., oy * 5., oy * 4.2, Color::new(0., 0., 0., 0.85));
+    draw_text("New game", ox + oy * 1.8, oy * 3.6, oy * 0.4, WHITE);
 
-    let mut options: Vec<(f32, (usize, usize, usize, usize))> = Vec::new();
+    for (i, player) in players.iter_mut().enumerate() {
+        let y = oy * (4.1 + i as f32 * 0.8);
+        draw_text(if i == 0 {"White"} else {"Black"}, ox + oy * 1.8, y + oy * 0.35, oy * 0.3, WHITE);
 
-    for m in vm {
-        let mut ar = board.clone();
+        let label = if *player == Player::Human {"Human"} else {"Engine"};
+        if button(label, ox + oy * 2.8, y, oy * 1.3, oy * 0.45) {
+            *player = if *player == Player::Human {Player::Engine(3)} else {Player::Human};
+        }
 
-        let mut eval = 0.;
+        if let Player::Engine(depth) = player {
+            if button("-", ox + oy * 4.3, y, oy * 0.45, oy * 0.45) {*depth = (*depth - 1).max(1)}
+            draw_text(&format!("depth {}", depth), ox + oy * 4.85, y + oy * 0.35, oy * 0.3, WHITE);
+            if button("+", ox + oy * 5.9, y, oy * 0.45, oy * 0.45) {*depth = (*depth + 1).min(MAX_STRENGTH)}
+        }
+    }
 
-        if ar[m.1][m.0].unwrap().white {continue}
+    draw_text("Clock", ox + oy * 1.8, oy * 6.05, oy * 0.3, WHITE);
+    let label = control.map_or("None".to_string(), |tc| tc.to_string());
+    if button(&label, ox + oy * 2.8, oy * 5.7, oy * 2.2, oy * 0.45) {
+        let presets = TIME_CONTROLS.map(|tc| TimeControl::parse(tc).unwrap());
+        *control = match presets.iter().position(|&tc| Some(tc) == *control) {
+            Some(i) => presets.get(i + 1).copied(),
+            None if control.is_none() => Some(presets[0]),
+            None => None,
+        };
+    }
 
-        ar[m.3][m.2] = ar[m.1][m.0];
-        ar[m.1][m.0] = None;
+    if button("Start", ox + oy * 2.8, oy * 6.5, oy * 1.3, oy * 0.45) {return Some(true)}
+    if button("Cancel", ox + oy * 4.3, oy * 6.5, oy * 1.3, oy * 0.45) {return Some(false)}
+    None
+}
 
-        eval += evaluate(ar);
+// Position setup: the position being built and what a left click puts on a square.
+struct Editor {
+    pos: Position,
+    // None empties squares.
+    brush: Option<Piece>,
+    error: String,
+}
 
-        let avm = valid_moves(ar, wmk, *bmk);
+enum Setup {
+    Play,
+    Analyse,
+    Cancel,
+}
 
-        let mut ao: Vec<(f32, (usize, usize, usize, usize))> = Vec::new();
+// Draws the position being set up with its piece palette and controls. Left click (or drag)
+// paints the brush, right click empties a square. Play and Analyse only go through once the
+// position is valid.
+fn setup_editor(editor: &mut Editor, texture: &dyn Fn(Kind) -> Texture2D, flipped: bool, ox: f32, oy: f32) -> Option<Setup> {
+    let pos = &mut editor.pos;
+
+    draw_rectangle(ox, oy, oy * 8., oy * 8., Color::new(1.0, 0.8, 0.6, 1.));
+    for (y, row) in pos.board.iter().enumerate() {
+        for (x, square) in row.iter().enumerate() {
+            let (cx, cy) = if flipped {(7 - x, 7 - y)} else {(x, y)};
+            let (sx, sy) = (ox + cx as f32 * oy, oy + cy as f32 * oy);
+
+            if (x + y) % 2 == 0 {draw_rectangle(sx, sy, oy, oy, Color::new(0.15, 0.4, 0.15, 1.))}
+            if pos.ep == Some((x, y)) {draw_circle(sx + oy / 2., sy + oy / 2., oy * 0.15, Color::new(0.9, 0.9, 0.2, 0.8))}
+            if let Some(piece) = square {draw_piece(texture(piece.kind), piece.white, sx, sy, oy)}
+        }
+    }
+
+    if let Some((x, y)) = square_at(mouse_position(), ox, oy, flipped) {
+        if is_mouse_button_down(MouseButton::Left) {
+            // A second king of the same colour takes the first one's place.
+            if let Some(brush) = editor.brush.filter(|p| p.kind == Kind::King) {
+                if let Some((kx, ky)) = pos.king(brush.white) {pos.board[ky][kx] = None}
+            }
+            pos.board[y][x] = editor.brush;
+        }
+        if is_mouse_button_down(MouseButton::Right) {pos.board[y][x] = None}
+    }
 
-        for m1 in avm {
-            let mut ar1 = ar;
+    let (bx, by) = (ox + oy * 8.3, oy);
+    let size = oy * 0.55;
+    let kinds = [Kind::King, Kind::Queen, Kind::Rook, Kind::Bishop, Kind::Knight, Kind::Pawn];
+    let (mx, my) = mouse_position();
 
-            if !ar1[m1.1][m1.0].unwrap().white {continue}
+    for (row, white) in [true, false].into_iter().enumerate() {
+        for (i, &kind) in kinds.iter().enumerate() {
+            let (px, py) = (bx + i as f32 * size, by + row as f32 * size);
+            let piece = Piece { kind, white };
 
-            ar1[m1.3][m1.2] = ar1[m1.1][m1.0];
-            ar1[m1.1][m1.0] = None;
+            draw_rectangle(px, py, size, size, Color::new(0.6, 0.5, 0.4, 1.));
+            draw_piece(texture(kind), white, px, py, size);
+            if editor.brush.is_some_and(|b| b.kind == kind && b.white == white) {
+                draw_rectangle_lines(px, py, size, size, oy * 0.06, YELLOW);
+            }
 
-            ao.push((evaluate(ar1), m1))
+            if is_mouse_button_pressed(MouseButton::Left) && mx >= px && mx < px + size && my >= py && my < py + size {
+                editor.brush = Some(piece);
+            }
         }
-        ao.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+    }
 
-        if ao.len() > 0 {
-            let am = ao[0].1;
+    if button("Empty square", bx, by + oy * 1.2, oy * 2.2, oy * 0.45) {editor.brush = None}
+    if editor.brush.is_none() {draw_rectangle_lines(bx, by + oy * 1.2, oy * 2.2, oy * 0.45, oy * 0.06, YELLOW)}
 
-            ao.clear();
+    if button("Clear", bx, by + oy * 1.9, oy * 2.2, oy * 0.45) {
+        (pos.board, pos.castling, pos.ep) = ([[None; 8]; 8], [false; 4], None);
+    }
+    if button("Start position", bx, by + oy * 2.45, oy * 2.2, oy * 0.45) {*pos = Position::start()}
 
-            ar[am.3][am.2] = ar[am.1][am.0];
-            ar[am.1][am.0] = None;
+    // Mirrors the board top to bottom and swaps the colours, so that the other side has the same position.
+    if button("Flip colours", bx, by + oy * 3., oy * 2.2, oy * 0.45) {
+        let board = pos.board;
+        for (y, row) in pos.board.iter_mut().enumerate() {
+            *row = board[7 - y].map(|square| square.map(|p| Piece { white: !p.white, ..p }));
+        }
+        let c = pos.castling;
+        (pos.white, pos.castling, pos.ep) = (!pos.white, [c[2], c[3], c[0], c[1]], pos.ep.map(|(x, y)| (x, 7 - y)));
+    }
 
-            if ar[am.3][am.2].unwrap().kind == Kind::Pawn && m.3 == 0 {
-                ar[m.3][m.2] = Piece::wq();
-            }
-            if ar[am.3][am.2].unwrap().kind == Kind::Pawn && m.3 == 7 {
-                ar[m.3][m.2] = Piece::bq();
-            }
+    if button(if pos.white {"White to move"} else {"Black to move"}, bx, by + oy * 3.7, oy * 2.2, oy * 0.45) {
+        pos.white = !pos.white;
+        pos.ep = None;
+    }
 
-            let avm = valid_moves(ar, wmk, *bmk);
+    draw_text("Castling", bx, by + oy * 4.6, oy * 0.3, WHITE);
+    for (i, label) in ["K", "Q", "k", "q"].into_iter().enumerate() {
+        let x = bx + oy * (1.2 + i as f32 * 0.5);
+        if button(label, x, by + oy * 4.3, oy * 0.45, oy * 0.45) {pos.castling[i] = !pos.castling[i]}
+        if pos.castling[i] {draw_rectangle_lines(x, by + oy * 4.3, oy * 0.45, oy * 0.45, oy * 0.06, YELLOW)}
+    }
 
-            for m1 in avm {
-                let mut ar1 = ar;
+    // Steps through the squares a pawn could be taken en passant on, then back to none.
+    let label = format!("En passant {}", pos.ep.map_or("-".to_string(), fen::square_name));
+    if button(&label, bx, by + oy * 4.9, oy * 2.2, oy * 0.45) {
+        let rank = if pos.white {2} else {5};
+        let squares: Vec<_> = (0..8).map(|x| (x, rank)).filter(|&sq| pos.en_passant_possible(sq)).collect();
+        pos.ep = match squares.iter().position(|&sq| Some(sq) == pos.ep) {
+            Some(i) => squares.get(i + 1).copied(),
+            None => squares.first().copied(),
+        };
+    }
 
-                if ar1[m1.1][m1.0].unwrap().white {continue}
+    let mut setup = None;
+    if button("Play", bx, by + oy * 5.9, oy * 2.2, oy * 0.45) {setup = Some(Setup::Play)}
+    if button("Analyse", bx, by + oy * 6.45, oy * 2.2, oy * 0.45) {setup = Some(Setup::Analyse)}
+    if button("Cancel", bx, by + oy * 7., oy * 2.2, oy * 0.45) {return Some(Setup::Cancel)}
 
-                ar1[m1.3][m1.2] = ar1[m1.1][m1.0];
-                ar1[m1.1][m1.0] = None;
+    draw_text(&fen::to_fen(pos), ox, oy * 9.4, oy * 0.28, LIGHTGRAY);
+    draw_text(&editor.error, ox, oy * 9.8, oy * 0.28, RED);
 
-                ao.push((evaluate(ar1), m1))
-            }
+    if setup.is_some() {
+        if let Err(e) = pos.validate() {
+            editor.error = e;
+            return None;
+        }
+    }
+    setup
+}
 
-            ao.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+#[derive(Clone)]
+enum Jump {
+    // Show the position after this many plies of the main line.
+    Ply(usize),
+    // Make the variation at this path (see `Game::promote`) the main line and show its nth move.
+    Variation(Vec<usize>, usize),
+}
 
-            for _ in 0..128 {
-                if ao.len() != 0 {
-                    eval += ao[ao.len() - 1].0;
+// Row, x offset, text, colour and target of an entry in the move list.
+type Cell = (usize, f32, String, Color, Jump);
 
-                    let am = ao[0].1;
+// Main line moves as numbered pairs, with the variations that branch off a move on grey rows
+// below it, and the ones nested in those indented further. Keeps the current move in view and
+// returns the move clicked on, if any.
+fn draw_move_list(game: &Game, view: usize, x: f32, y: f32, w: f32, h: f32, oy: f32) -> Option<Jump> {
+    let size = oy * 0.28;
+    let height = oy * 0.36;
 
-                    ao.clear();
+    let mut cells: Vec<Cell> = Vec::new();
+    let mut rows = 0;
+    let mut current = 0;
 
-                    ar[am.3][am.2] = ar[am.1][am.0];
-                    ar[am.1][am.0] = None;
+    for (i, record) in game.moves.iter().enumerate() {
+        let pos = &game.positions[i];
 
-                    if ar[am.3][am.2].unwrap().kind == Kind::Pawn && m.3 == 0 {
-                    ar[m.3][m.2] = Piece::wq();
-                    }
-                    if ar[am.3][am.2].unwrap().kind == Kind::Pawn && m.3 == 7 {
-                        ar[m.3][m.2] = Piece::bq();
-                    }
+        if pos.white || i == 0 {
+            rows += 1;
+            cells.push((rows - 1, 0., format!("{}{}", pos.fullmove, if pos.white {"."} else {"..."}), GRAY, Jump::Ply(i + 1)));
+        }
+        cells.push((rows - 1, if pos.white {oy * 0.7} else {oy * 1.9}, record.san.clone(), if i + 1 == view {YELLOW} else {WHITE}, Jump::Ply(i + 1)));
+        if i + 1 == view {current = rows - 1}
 
-                    if ar[am.3][am.2].unwrap().kind == Kind::Pawn && m.3 == 0 {
-                        ar[m.3][m.2] = Piece::wq();
-                    }
+        let mut interrupted = false;
+        for (index, v) in game.variations.iter().enumerate().filter(|(_, v)| v.ply == i) {
+            variation_cells(&mut cells, &mut rows, *pos, v, vec![index], 1, w, oy);
+            interrupted = true;
+        }
+
+        if interrupted && pos.white && i + 1 < game.moves.len() {
+            rows += 1;
+            cells.push((rows - 1, 0., format!("{}...", pos.fullmove), GRAY, Jump::Ply(i + 2)));
+        }
+    }
 
-                    let avm = valid_moves(ar, wmk, *bmk);
+    draw_rectangle(x, y, w, h, Color::new(0., 0., 0., 0.3));
 
-                    for m1 in avm {
-                        let mut ar1 = ar;
+    let visible = (h / height) as usize;
+    let first = (current + 1).saturating_sub(visible);
+    let (mx, my) = mouse_position();
+    let mut jump = None;
 
-                        if ar1[m1.1][m1.0].unwrap().white {continue}
+    for (row, cx, text, color, target) in cells {
+        if row < first || row >= first + visible {continue}
 
-                        ar1[m1.3][m1.2] = ar1[m1.1][m1.0];
-                        ar1[m1.1][m1.0] = None;
+        let (tx, ty) = (x + oy * 0.1 + cx, y + (row - first) as f32 * height);
+        draw_text(&text, tx, ty + height * 0.8, size, color);
 
-                        ao.push((evaluate(ar1), m1))
-                    }
+        let width = measure_text(&text, None, size as u16, 1.).width;
+        if is_mouse_button_pressed(MouseButton::Left) && mx >= tx && mx < tx + width && my >= ty && my < ty + height {
+            jump = Some(target);
+        }
+    }
 
-                    ao.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
-                }
-            }
+    jump
+}
+
+// The moves of `v`, played from `pos`, on rows indented `depth` steps, wrapped to `w`. The lines
+// nested in it go on rows of their own after the move they replace, and `v` carries on below them.
+#[allow(clippy::too_many_arguments)]
+fn variation_cells(cells: &mut Vec<Cell>, rows: &mut usize, mut pos: Position, v: &Variation, path: Vec<usize>, depth: usize, w: f32, oy: f32) {
+    let indent = oy * 0.3 * depth as f32;
+    let mut cx = indent;
+    let mut numbered = false;
+    *rows += 1;
+
+    for (n, r) in v.moves.iter().enumerate() {
+        let text = if pos.white || !numbered {format!("{}{} {}", pos.fullmove, if pos.white {"."} else {"..."}, r.san)} else {r.san.clone()};
+        let width = measure_text(&text, None, (oy * 0.28) as u16, 1.).width + oy * 0.12;
+        numbered = true;
+
+        if cx + width > w && cx > indent {
+            *rows += 1;
+            cx = indent;
+        }
+        cells.push((*rows - 1, cx, text, GRAY, Jump::Variation(path.clone(), n)));
+        cx += width;
+
+        let mut interrupted = false;
+        for (index, nested) in v.variations.iter().enumerate().filter(|(_, nested)| nested.ply == v.ply + n) {
+            let path = path.iter().copied().chain([index]).collect();
+            variation_cells(cells, rows, pos, nested, path, depth + 1, w, oy);
+            interrupted = true;
+        }
+
+        if interrupted && n + 1 < v.moves.len() {
+            *rows += 1;
+            cx = indent;
+            numbered = false;
         }
+        pos = pos.make(r.m);
+    }
+}
 
-        if ao.len() != 0 {eval += ao[ao.len() - 1].0}
+// A row of buttons over the board; returns the index of the one pressed.
+fn choice_dialog(title: &str, options: &[&str], ox: f32, oy: f32) -> Option<usize> {
+    draw_rectangle(ox + oy * 1., oy * 3.5, oy * 6., oy * 2., Color::new(0., 0., 0., 0.85));
+    draw_text(title, ox + oy * 1.3, oy * 4.1, oy * 0.35, WHITE);
+
+    let mut x = ox + oy * 1.3;
+    let mut choice = None;
+    for (i, option) in options.iter().enumerate() {
+        let w = measure_text(option, None, (oy * 0.27) as u16, 1.).width + oy * 0.3;
+        if button(option, x, oy * 4.6, w, oy * 0.45) {choice = Some(i)}
+        x += w + oy * 0.15;
+    }
+    choice
+}
 
-        options.push((eval, m));
+fn draw_piece(texture: Texture2D, white: bool, x: f32, y: f32, size: f32) {
+    let color = if white {WHITE} else {Color::new(0.25, 0.25, 0.25, 1.)};
+    draw_texture_ex(texture, x, y, color, DrawTextureParams { dest_size: Some(Vec2::new(size, size)), ..Default::default() });
+}
+
+fn square_at((mx, my): (f32, f32), ox: f32, oy: f32, flipped: bool) -> Option<(usize, usize)> {
+    if mx < ox || my < oy || mx >= ox + oy * 8. || my >= oy * 9. {return None}
+
+    let (x, y) = (((mx - ox) / oy) as usize, ((my - oy) / oy) as usize);
+    Some(if flipped {(7 - x, 7 - y)} else {(x, y)})
+}
+
+// The pieces a move carries across the board: the mover, and the rook when castling.
+fn slides(before: &Position, m: Move) -> Vec<((usize, usize), (usize, usize))> {
+    let mut slides = vec![((m.0, m.1), (m.2, m.3))];
+    if before.is_castle(m) {
+        slides.push(if m.2 > m.0 {((7, m.1), (5, m.1))} else {((0, m.1), (3, m.1))});
     }
+    slides
+}
 
-    options.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+fn button(label: &str, x: f32, y: f32, w: f32, h: f32) -> bool {
+    draw_rectangle(x, y, w, h, Color::new(0.15, 0.15, 0.15, 1.));
+    draw_text(label, x + h * 0.3, y + h * 0.7, h * 0.6, WHITE);
 
-    let m = options[options.len() - 1].1;
+    let (mx, my) = mouse_position();
+    is_mouse_button_pressed(MouseButton::Left) && mx > x && mx < x + w && my > y && my < y + h
+}
+
+// The engine searching a position on a thread of its own until dropped, with the last iteration
+// it finished and when.
+struct Analysis {
+    pos: Position,
+    // Hashes of the positions up to and including `pos`.
+    history: Vec<u64>,
+    stop: Arc<AtomicBool>,
+    latest: Arc<Mutex<Option<(search::SearchResult, Duration)>>>,
+}
+
+impl Analysis {
+    fn start(pos: Position, history: Vec<u64>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let latest = Arc::new(Mutex::new(None));
+
+        let (before, stopped, found) = (history[..history.len() - 1].to_vec(), stop.clone(), latest.clone());
+        std::thread::spawn(move || {
+            let start = Instant::now();
+            search::search_with(&pos, &before, search::Limits::infinite(), &stopped, |r| {
+                *found.lock().unwrap() = Some((r.clone(), start.elapsed()));
+            });
+        });
 
-    board[m.3][m.2] = board[m.1][m.0];
-    board[m.1][m.0] = None;
+        Analysis { pos, history, stop, latest }
+    }
+
+    fn latest(&self) -> Option<(search::SearchResult, Duration)> {
+        self.latest.lock().unwrap().clone()
+    }
 
-    if let Some(piece) = board[m.3][m.2] {
-        if piece.kind == Kind::Pawn && m.3 == 0 {
-            board[m.3][m.2] = Piece::bq();
+    // Depth, score from white's side, nodes and speed on one line, and the principal variation
+    // in SAN, cut to fit `width`, on the other; checkmate or stalemate instead once there are no moves.
+    fn summary(&self, width: f32, size: f32) -> (String, String) {
+        let Some((r, elapsed)) = self.latest() else {return ("analysing...".to_string(), String::new())};
+        if r.best.is_none() {
+            return (if self.pos.in_check(self.pos.white) {"checkmate"} else {"stalemate"}.to_string(), String::new());
         }
 
-        if !*bmk && piece.kind == Kind::King {
-            if m.3 == 6 && m.2 == 7 && m.0 == 4 {
-                board[7][7] = None;
-                board[7][5] = Piece::wr();
-            } else if m.3 == 2 && m.2 == 7 && m.0 == 4 {
-                board[7][0] = None;
-                board[7][3] = Piece::wr();
-            }
+        let score = if self.pos.white {r.score} else {-r.score};
+        let score = if search::is_mate_score(score) {
+            format!("#{}{}", if score > 0 {""} else {"-"}, (search::MATE - score.abs() + 1) / 2)
+        } else {
+            format!("{:+.2}", score as f32 / 100.)
+        };
+        let nps = r.nodes as f64 / elapsed.as_secs_f64().max(0.001);
+        let info = format!("depth {}  {}  nodes {}  {:.0} kN/s", r.depth, score, r.nodes, nps / 1000.);
+
+        let mut line = String::new();
+        let mut p = self.pos;
+        for (i, &m) in r.pv.iter().enumerate() {
+            let next = p.make(m);
+            let number = if p.white {format!("{}. ", p.fullmove)} else if i == 0 {format!("{}... ", p.fullmove)} else {String::new()};
+            let token = format!("{}{} ", number, san::format(&p, m, &next));
+
+            if measure_text(&(line.clone() + &token), None, size as u16, 1.).width > width {break}
+            line += &token;
+            p = next;
+        }
 
-            *bmk = true;
+        (info, line)
+    }
+}
+
+impl Drop for Analysis {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn draw_eval_panel(trace: &Trace, ox: f32, oy: f32) {
+    let size = oy * 0.3;
+    let columns = [oy * 2.6, oy * 3.6, oy * 4.6, oy * 5.6, oy * 6.8];
+
+    draw_rectangle(ox + oy * 0.2, oy * 1.2, oy * 7.6, oy * 5.6, Color::new(0., 0., 0., 0.8));
+
+    let mut ty = oy * 1.6;
+
+    for (i, header) in ["W mg", "W eg", "B mg", "B eg", "Total"].iter().enumerate() {
+        draw_text(header, ox + columns[i], ty, size, GRAY);
+    }
+
+    for &term in trace::TERMS.iter() {
+        ty += oy * 0.4;
+
+        let [w, b] = trace.terms[term as usize];
+
+        draw_text(term.name(), ox + oy * 0.4, ty, size, WHITE);
+
+        for (i, v) in [w.0, w.1, b.0, b.1, trace.term(term)].iter().enumerate() {
+            draw_text(&format!("{:.2}", v), ox + columns[i], ty, size, WHITE);
         }
     }
+
+    ty += oy * 0.6;
+    draw_text(&format!("Phase {:.2}   Total {:.2}", trace.phase, trace.total()), ox + oy * 0.4, ty, size, YELLOW);
 }
 
-fn evaluate(board: [[Option<Piece>; 8]; 8]) -> f32 {
-    let mut score = 0.;
+// The engine choosing its move on a thread of its own, stopped when dropped: a book move if
+// there is one, otherwise the result of a search to `depth` plies, cut short when the game has a
+// clock and `time` runs out, along with the score it searched to from white's side.
+struct Thinking {
+    // Hashes of the positions up to and including the one to move from.
+    history: Vec<u64>,
+    depth: u32,
+    stop: Arc<AtomicBool>,
+    chosen: Arc<Mutex<Option<Option<Choice>>>>,
+}
 
-    for y in 0..8 {
-        for x in 0..8 {
-            if let Some(piece) = board[y][x] {
+impl Thinking {
+    fn start(pos: Position, history: Vec<u64>, depth: u32, time: Option<Duration>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let chosen = Arc::new(Mutex::new(None));
+
+        let (before, stopped, found) = (history[..history.len() - 1].to_vec(), stop.clone(), chosen.clone());
+        std::thread::spawn(move || {
+            let m = book::pick(&pos).map(|m| (m, None)).or_else(|| {
+                let r = search::search_with(&pos, &before, search::Limits { time, ..search::Limits::depth(depth) }, &stopped, |_| {});
+                r.best.map(|m| (m, Some(pawns(r.score, pos.white))))
+            });
+            *found.lock().unwrap() = Some(m);
+        });
+
+        Thinking { history, depth, stop, chosen }
+    }
+
+    // The move and its score, or None for no move, once the engine has decided.
+    fn chosen(&self) -> Option<Option<Choice>> {
+        *self.chosen.lock().unwrap()
+    }
+}
+
+// A move the engine chose, with its score when it came from a search rather than the book.
+type Choice = (Move, Option<f32>);
+
+impl Drop for Thinking {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+// A search score for the side to move, in pawns from white's side.
+fn pawns(score: i32, white: bool) -> f32 {
+    (if white {score} else {-score}) as f32 / 100.
+}
+
+fn evaluate(board: [[Option<Piece>; 8]; 8], white_to_move: bool) -> f32 {
+    endgame::evaluate(&board, white_to_move, || {
+        if nnue::enabled() {return nnue::evaluate(&board)}
+
+        evaluate_trace(&board, &params::get(), false).total()
+    })
+}
+
+fn evaluate_trace(board: &[[Option<Piece>; 8]; 8], params: &EvalParams, detailed: bool) -> Trace {
+    let mut trace = Trace::new(detailed);
+    trace.phase = phase(board);
+
+    for (y, row) in board.iter().enumerate() {
+        for (x, square) in row.iter().enumerate() {
+            if let Some(piece) = square {
+                let material = params.piece_values[piece.kind.index()];
                 let mut s = 0.;
                 
                 match piece.kind {
                     Kind::Pawn => {
-                        if piece.white {s += (piece.kind as i32) as f32 * (7-y) as f32 * 0.5}
-                        else {s += (piece.kind as i32) as f32 * y as f32 * 0.5}
+                        if piece.white {s += material * (7-y) as f32 * params.pawn_advance}
+                        else {s += material * y as f32 * params.pawn_advance}
                     },
                     Kind::Knight => {
-                        s += (piece.kind as i32) as f32 * HEAT_MAP[y][x];
+                        s += material * params.heat_map[y][x];
                     },
                     Kind::Bishop => {
-                        s += (piece.kind as i32) as f32 * HEAT_MAP[y][x];
+                        s += material * params.heat_map[y][x];
                     },
                     Kind::Rook => {
-                        s += (piece.kind as i32) as f32 * 2.5;
+                        s += material * params.rook_scale;
                     },
                     Kind::Queen => {
-                        s += (piece.kind as i32) as f32 * 2.5;
+                        s += material * params.queen_scale;
                     },
                     Kind::King => {
-                        s += (piece.kind as i32) as f32;
+                        s += material;
                     },
                 }
 
-                if piece.white {score += s}
-                else {score -= s}
+                trace.add(Term::Material, piece.white, material, material);
+                trace.add_at(Term::Pst, piece.white, s - material, s - material, x, y);
             }
         }
     }
 
-    return score;
+    pawns::evaluate(board, params, &mut trace);
+    king_safety::evaluate(board, params, &mut trace);
+    pieces::evaluate(board, params, &mut trace);
+
+    trace
+}
+
+fn phase(board: &[[Option<Piece>; 8]; 8]) -> f32 {
+    let mut phase = 0.;
+
+    for row in board {
+        for piece in row.iter().flatten() {
+            phase += match piece.kind {
+                Kind::Knight | Kind::Bishop => 1.,
+                Kind::Rook => 2.,
+                Kind::Queen => 4.,
+                _ => 0.,
+            };
+        }
+    }
+
+    f32::min(phase / 24., 1.)
 }
 
 fn valid_moves(board: [[Option<Piece>; 8]; 8], wmk: bool, bmk: bool) -> Vec<(usize, usize, usize, usize)> {
@@ -471,7 +1336,7 @@ fn valid_moves(board: [[Option<Piece>; 8]; 8], wmk: bool, bmk: bool) -> Vec<(usi
                                 }
                             }
                         } else {
-                            if y+1 < 7 && board[y+1][x].is_none() {
+                            if y+1 < 8 && board[y+1][x].is_none() {
                                 valid.push((x, y, x, y+1));
 
                                 if y == 1 && board[y+2][x].is_none() {
@@ -547,42 +1412,50 @@ fn valid_moves(board: [[Option<Piece>; 8]; 8], wmk: bool, bmk: bool) -> Vec<(usi
                                 if let Some(piece) = board[y-2][x-1] {
                                     if piece.white {valid.push((x, y, x-1, y-2))};
                                 }
+                                else {valid.push((x, y, x-1, y-2))}
                             }
                             if y > 1 && x < 7 {
                                 if let Some(piece) = board[y-2][x+1] {
                                     if piece.white {valid.push((x, y, x+1, y-2))};
                                 }
+                                else {valid.push((x, y, x+1, y-2))}
                             }
                             if y < 6 && x > 0 {
                                 if let Some(piece) = board[y+2][x-1] {
                                     if piece.white {valid.push((x, y, x-1, y+2))};
                                 }
+                                else {valid.push((x, y, x-1, y+2))}
                             }
                             if y < 6 && x < 7 {
                                 if let Some(piece) = board[y+2][x+1] {
                                     if piece.white {valid.push((x, y, x+1, y+2))};
                                 }
+                                else {valid.push((x, y, x+1, y+2))}
                             }
 
                             if x > 1 && y > 0 {
                                 if let Some(piece) = board[y-1][x-2] {
                                     if piece.white {valid.push((x, y, x-2, y-1))};
                                 }
+                                else {valid.push((x, y, x-2, y-1))}
                             }
                             if x < 6 && y > 0 {
                                 if let Some(piece) = board[y-1][x+2] {
                                     if piece.white {valid.push((x, y, x+2, y-1))};
                                 }
+                                else {valid.push((x, y, x+2, y-1))}
                             }
                             if x > 1 && y < 7 {
                                 if let Some(piece) = board[y+1][x-2] {
                                     if piece.white {valid.push((x, y, x-2, y+1))};
                                 }
+                                else {valid.push((x, y, x-2, y+1))}
                             }
                             if x < 6 && y < 7 {
                                 if let Some(piece) = board[y+1][x+2] {
                                     if piece.white {valid.push((x, y, x+2, y+1))};
                                 }
+                                else {valid.push((x, y, x+2, y+1))}
                             }
                         }
                     },
@@ -597,7 +1470,7 @@ fn valid_moves(board: [[Option<Piece>; 8]; 8], wmk: bool, bmk: bool) -> Vec<(usi
                         }
 
                         for xy in 1..8 {
-                            if x - xy > 7 || y + xy > 7 {break}
+                            if xy > x || y + xy > 7 {break}
                             if let Some(p) = board[y + xy][x - xy] {
                                 if (piece.white && !p.white) || (!piece.white && p.white) {valid.push((x, y, x - xy, y + xy))};
                                 break;
@@ -606,7 +1479,7 @@ fn valid_moves(board: [[Option<Piece>; 8]; 8], wmk: bool, bmk: bool) -> Vec<(usi
                         }
 
                         for xy in 1..8 {
-                            if x + xy > 7 || y - xy > 7 {break}
+                            if x + xy > 7 || xy > y {break}
                             if let Some(p) = board[y - xy][x + xy] {
                                 if (piece.white && !p.white) || (!piece.white && p.white) {valid.push((x, y, x + xy, y - xy))};
                                 break;
@@ -615,7 +1488,7 @@ fn valid_moves(board: [[Option<Piece>; 8]; 8], wmk: bool, bmk: bool) -> Vec<(usi
                         }
 
                         for xy in 1..8 {
-                            if x - xy > 7 || y - xy > 7 {break}
+                            if xy > x || xy > y {break}
                             if let Some(p) = board[y - xy][x - xy] {
                                 if (piece.white && !p.white) || (!piece.white && p.white) {valid.push((x, y, x - xy, y - xy))};
                                 break;
@@ -634,7 +1507,7 @@ fn valid_moves(board: [[Option<Piece>; 8]; 8], wmk: bool, bmk: bool) -> Vec<(usi
                         }
 
                         for xy in 1..8 {
-                            if x - xy > 7 {break}
+                            if xy > x {break}
                             if let Some(p) = board[y][x - xy] {
                                 if (piece.white && !p.white) || (!piece.white && p.white) {valid.push((x, y, x - xy, y))};
                                 break;
@@ -652,7 +1525,7 @@ fn valid_moves(board: [[Option<Piece>; 8]; 8], wmk: bool, bmk: bool) -> Vec<(usi
                         }
 
                         for xy in 1..8 {
-                            if y - xy > 7 {break}
+                            if xy > y {break}
                             if let Some(p) = board[y - xy][x] {
                                 if (piece.white && !p.white) || (!piece.white && p.white) {valid.push((x, y, x, y - xy))};
                                 break;
@@ -671,7 +1544,7 @@ fn valid_moves(board: [[Option<Piece>; 8]; 8], wmk: bool, bmk: bool) -> Vec<(usi
                         }
 
                         for xy in 1..8 {
-                            if x - xy > 7 || y + xy > 7 {break}
+                            if xy > x || y + xy > 7 {break}
                             if let Some(p) = board[y + xy][x - xy] {
                                 if (piece.white && !p.white) || (!piece.white && p.white) {valid.push((x, y, x - xy, y + xy))};
                                 break;
@@ -680,7 +1553,7 @@ fn valid_moves(board: [[Option<Piece>; 8]; 8], wmk: bool, bmk: bool) -> Vec<(usi
                         }
 
                         for xy in 1..8 {
-                            if x + xy > 7 || y - xy > 7 {break}
+                            if x + xy > 7 || xy > y {break}
                             if let Some(p) = board[y - xy][x + xy] {
                                 if (piece.white && !p.white) || (!piece.white && p.white) {valid.push((x, y, x + xy, y - xy))};
                                 break;
@@ -689,7 +1562,7 @@ fn valid_moves(board: [[Option<Piece>; 8]; 8], wmk: bool, bmk: bool) -> Vec<(usi
                         }
 
                         for xy in 1..8 {
-                            if x - xy > 7 || y - xy > 7 {break}
+                            if xy > x || xy > y {break}
                             if let Some(p) = board[y - xy][x - xy] {
                                 if (piece.white && !p.white) || (!piece.white && p.white) {valid.push((x, y, x - xy, y - xy))};
                                 break;
@@ -707,7 +1580,7 @@ fn valid_moves(board: [[Option<Piece>; 8]; 8], wmk: bool, bmk: bool) -> Vec<(usi
                         }
 
                         for xy in 1..8 {
-                            if x - xy > 7 {break}
+                            if xy > x {break}
                             if let Some(p) = board[y][x - xy] {
                                 if (piece.white && !p.white) || (!piece.white && p.white) {valid.push((x, y, x - xy, y))};
                                 break;
@@ -725,7 +1598,7 @@ fn valid_moves(board: [[Option<Piece>; 8]; 8], wmk: bool, bmk: bool) -> Vec<(usi
                         }
 
                         for xy in 1..8 {
-                            if y - xy > 7 {break}
+                            if xy > y {break}
                             if let Some(p) = board[y - xy][x] {
                                 if (piece.white && !p.white) || (!piece.white && p.white) {valid.push((x, y, x, y - xy))};
                                 break;
@@ -872,4 +1745,37 @@ fn valid_moves(board: [[Option<Piece>; 8]; 8], wmk: bool, bmk: bool) -> Vec<(usi
     }
 
     valid
-}
\ No newline at end of file
+}
+
+fn attacks(board: &[[Option<Piece>; 8]; 8], x: usize, y: usize) -> Vec<(usize, usize)> {
+    let mut attacked = Vec::new();
+
+    let Some(piece) = board[y][x] else {return attacked};
+
+    let (steps, slides): (&[(isize, isize)], bool) = match piece.kind {
+        Kind::Pawn => {
+            if piece.white {(&[(-1, -1), (1, -1)], false)}
+            else {(&[(-1, 1), (1, 1)], false)}
+        },
+        Kind::Knight => (&[(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)], false),
+        Kind::Bishop => (&[(1, 1), (1, -1), (-1, 1), (-1, -1)], true),
+        Kind::Rook => (&[(1, 0), (-1, 0), (0, 1), (0, -1)], true),
+        Kind::Queen => (&[(1, 1), (1, -1), (-1, 1), (-1, -1), (1, 0), (-1, 0), (0, 1), (0, -1)], true),
+        Kind::King => (&[(1, 1), (1, -1), (-1, 1), (-1, -1), (1, 0), (-1, 0), (0, 1), (0, -1)], false),
+    };
+
+    for (dx, dy) in steps {
+        let (mut tx, mut ty) = (x as isize + dx, y as isize + dy);
+
+        while (0..8).contains(&tx) && (0..8).contains(&ty) {
+            attacked.push((tx as usize, ty as usize));
+
+            if !slides || board[ty as usize][tx as usize].is_some() {break}
+
+            tx += dx;
+            ty += dy;
+        }
+    }
+
+    attacked
+}
@@ -0,0 +1,278 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::params::EvalParams;
+use crate::position::Move;
+use crate::{Kind, Piece};
+
+// 768 -> HIDDEN -> 1: one input per (colour, piece kind, square), a clipped ReLU
+// hidden layer held in an incrementally updated accumulator, and a linear output.
+pub const INPUTS: usize = 768;
+pub const HIDDEN: usize = 128;
+
+const QA: i32 = 255;
+const QB: i32 = 64;
+
+const MAGIC: &[u8; 8] = b"CHESSNN1";
+
+pub struct Network {
+    w1: Vec<[i16; HIDDEN]>,
+    b1: [i16; HIDDEN],
+    w2: [i16; HIDDEN],
+    b2: i32,
+}
+
+// The compiled-in network is used until a file is loaded.
+static NETWORK: RwLock<Option<Arc<Network>>> = RwLock::new(None);
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn network() -> Arc<Network> {
+    if let Some(net) = NETWORK.read().unwrap().clone() {return net}
+    NETWORK.write().unwrap().get_or_insert_with(|| Arc::new(Network::material())).clone()
+}
+
+// Replaces the network in use by the one in `path`.
+pub fn load(path: &str) -> Result<(), String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let net = Network::from_bytes(&bytes).map_err(|e| format!("{}: {}", path, e))?;
+
+    *NETWORK.write().unwrap() = Some(Arc::new(net));
+    Ok(())
+}
+
+// Goes back to the compiled-in network.
+pub fn unload() {
+    *NETWORK.write().unwrap() = None;
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(on: bool) {
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+pub fn evaluate(board: &[[Option<Piece>; 8]; 8]) -> f32 {
+    let net = network();
+    Accumulator::refresh(&net, board).output(&net)
+}
+
+fn feature(piece: Piece, x: usize, y: usize) -> usize {
+    ((!piece.white as usize) * 6 + piece.kind.index()) * 64 + y * 8 + x
+}
+
+impl Network {
+    // Compiled-in fallback: one hidden unit per (colour, kind) counting material, so the
+    // network reproduces the classical piece values until a trained file is loaded.
+    fn material() -> Self {
+        const UNIT: i16 = 24;
+
+        let mut net = Network {
+            w1: vec![[0; HIDDEN]; INPUTS],
+            b1: [0; HIDDEN],
+            w2: [0; HIDDEN],
+            b2: 0,
+        };
+
+        let values = EvalParams::DEFAULT.piece_values;
+
+        for c in 0..2 {
+            for (k, value) in values.iter().enumerate().take(5) {
+                let neuron = c * 5 + k;
+
+                for sq in 0..64 {
+                    net.w1[(c * 6 + k) * 64 + sq][neuron] = UNIT;
+                }
+
+                let w = (value * (QA * QB) as f32 / UNIT as f32).round() as i16;
+                net.w2[neuron] = if c == 0 {w} else {-w};
+            }
+        }
+
+        net
+    }
+
+    // Little-endian: magic, u32 hidden size, w1 (INPUTS * HIDDEN i16), b1, w2 (HIDDEN i16), b2 (i32).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let expected = MAGIC.len() + 4 + (INPUTS * HIDDEN + 2 * HIDDEN) * 2 + 4;
+
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err("not a network file (bad magic)".to_string());
+        }
+        if bytes.len() != expected {
+            return Err(format!("expected {} bytes, found {}", expected, bytes.len()));
+        }
+
+        let hidden = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        if hidden != HIDDEN {
+            return Err(format!("network has {} hidden units, this build expects {}", hidden, HIDDEN));
+        }
+
+        let mut words = bytes[12..bytes.len() - 4].chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]]));
+
+        let mut net = Network {
+            w1: vec![[0; HIDDEN]; INPUTS],
+            b1: [0; HIDDEN],
+            w2: [0; HIDDEN],
+            b2: i32::from_le_bytes(bytes[bytes.len() - 4..].try_into().unwrap()),
+        };
+
+        for row in net.w1.iter_mut() {
+            row.iter_mut().for_each(|w| *w = words.next().unwrap());
+        }
+        net.b1.iter_mut().for_each(|w| *w = words.next().unwrap());
+        net.w2.iter_mut().for_each(|w| *w = words.next().unwrap());
+
+        Ok(net)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Accumulator {
+    values: [i16; HIDDEN],
+}
+
+impl Accumulator {
+    pub fn refresh(net: &Network, board: &[[Option<Piece>; 8]; 8]) -> Self {
+        let mut acc = Accumulator { values: net.b1 };
+
+        for (y, row) in board.iter().enumerate() {
+            for (x, square) in row.iter().enumerate() {
+                if let Some(piece) = square {
+                    simd::add(&mut acc.values, &net.w1[feature(*piece, x, y)]);
+                }
+            }
+        }
+
+        acc
+    }
+
+    // Applies the feature changes of move `m` played on `board` (the position before the move).
+    pub fn make(&mut self, net: &Network, board: &[[Option<Piece>; 8]; 8], m: Move) {
+        let (removed, added) = changes(board, m);
+
+        for &(piece, x, y) in removed.iter().flatten() {
+            simd::sub(&mut self.values, &net.w1[feature(piece, x, y)]);
+        }
+        for &(piece, x, y) in added.iter().flatten() {
+            simd::add(&mut self.values, &net.w1[feature(piece, x, y)]);
+        }
+    }
+
+    pub fn unmake(&mut self, net: &Network, board: &[[Option<Piece>; 8]; 8], m: Move) {
+        let (removed, added) = changes(board, m);
+
+        for &(piece, x, y) in added.iter().flatten() {
+            simd::sub(&mut self.values, &net.w1[feature(piece, x, y)]);
+        }
+        for &(piece, x, y) in removed.iter().flatten() {
+            simd::add(&mut self.values, &net.w1[feature(piece, x, y)]);
+        }
+    }
+
+    pub fn output(&self, net: &Network) -> f32 {
+        (simd::crelu_dot(&self.values, &net.w2, QA as i16) + net.b2) as f32 / (QA * QB) as f32
+    }
+}
+
+type Change = [Option<(Piece, usize, usize)>; 2];
+
+fn changes(board: &[[Option<Piece>; 8]; 8], m: Move) -> (Change, Change) {
+    let (x1, y1, x2, y2, promotion) = m;
+
+    let Some(piece) = board[y1][x1] else {return ([None; 2], [None; 2])};
+
+    let mut removed = [Some((piece, x1, y1)), board[y2][x2].map(|p| (p, x2, y2))];
+    let mut added = [None; 2];
+
+    if piece.kind == Kind::Pawn && x1 != x2 && board[y2][x2].is_none() {
+        removed[1] = board[y1][x2].map(|p| (p, x2, y1));
+    }
+
+    let placed = if piece.kind == Kind::Pawn && (y2 == 0 || y2 == 7) {
+        Piece { kind: promotion.unwrap_or(Kind::Queen), white: piece.white }
+    } else {
+        piece
+    };
+    added[0] = Some((placed, x2, y2));
+
+    if piece.kind == Kind::King && x1.abs_diff(x2) == 2 {
+        let (from, to) = if x2 > x1 {(7, 5)} else {(0, 3)};
+
+        if let Some(rook) = board[y1][from] {
+            removed[1] = Some((rook, from, y1));
+            added[1] = Some((rook, to, y1));
+        }
+    }
+
+    (removed, added)
+}
+
+mod simd {
+    use super::HIDDEN;
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn add(acc: &mut [i16; HIDDEN], row: &[i16; HIDDEN]) {
+        use std::arch::x86_64::*;
+
+        // SSE2 is part of the x86_64 baseline, so no runtime detection is needed.
+        unsafe {
+            for i in (0..HIDDEN).step_by(8) {
+                let a = _mm_loadu_si128(acc.as_ptr().add(i) as *const __m128i);
+                let b = _mm_loadu_si128(row.as_ptr().add(i) as *const __m128i);
+                _mm_storeu_si128(acc.as_mut_ptr().add(i) as *mut __m128i, _mm_add_epi16(a, b));
+            }
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn sub(acc: &mut [i16; HIDDEN], row: &[i16; HIDDEN]) {
+        use std::arch::x86_64::*;
+
+        unsafe {
+            for i in (0..HIDDEN).step_by(8) {
+                let a = _mm_loadu_si128(acc.as_ptr().add(i) as *const __m128i);
+                let b = _mm_loadu_si128(row.as_ptr().add(i) as *const __m128i);
+                _mm_storeu_si128(acc.as_mut_ptr().add(i) as *mut __m128i, _mm_sub_epi16(a, b));
+            }
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn crelu_dot(acc: &[i16; HIDDEN], weights: &[i16; HIDDEN], max: i16) -> i32 {
+        use std::arch::x86_64::*;
+
+        unsafe {
+            let zero = _mm_setzero_si128();
+            let top = _mm_set1_epi16(max);
+            let mut sum = _mm_setzero_si128();
+
+            for i in (0..HIDDEN).step_by(8) {
+                let a = _mm_loadu_si128(acc.as_ptr().add(i) as *const __m128i);
+                let w = _mm_loadu_si128(weights.as_ptr().add(i) as *const __m128i);
+                let a = _mm_min_epi16(_mm_max_epi16(a, zero), top);
+                sum = _mm_add_epi32(sum, _mm_madd_epi16(a, w));
+            }
+
+            let mut lanes = [0i32; 4];
+            _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, sum);
+            lanes.iter().sum()
+        }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn add(acc: &mut [i16; HIDDEN], row: &[i16; HIDDEN]) {
+        acc.iter_mut().zip(row).for_each(|(a, b)| *a = a.wrapping_add(*b));
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn sub(acc: &mut [i16; HIDDEN], row: &[i16; HIDDEN]) {
+        acc.iter_mut().zip(row).for_each(|(a, b)| *a = a.wrapping_sub(*b));
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn crelu_dot(acc: &[i16; HIDDEN], weights: &[i16; HIDDEN], max: i16) -> i32 {
+        acc.iter().zip(weights).map(|(&a, &w)| a.clamp(0, max) as i32 * w as i32).sum()
+    }
+}
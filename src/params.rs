@@ -0,0 +1,294 @@
+use std::sync::{RwLock, RwLockReadGuard};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EvalParams {
+    pub piece_values: [f32; 6],
+    pub heat_map: [[f32; 8]; 8],
+    pub pawn_advance: f32,
+    pub rook_scale: f32,
+    pub queen_scale: f32,
+
+    pub doubled: [f32; 2],
+    pub isolated: [f32; 2],
+    pub backward: [f32; 2],
+    pub connected: [f32; 2],
+    pub passed_mg: [f32; 8],
+    pub passed_eg: [f32; 8],
+    pub free_path: f32,
+    pub king_proximity_own: f32,
+    pub king_proximity_enemy: f32,
+
+    pub shield: [f32; 8],
+    pub storm: [f32; 8],
+    pub open_file: f32,
+    pub semi_open_file: f32,
+    pub attack_weight: [f32; 6],
+    pub safety_scale: f32,
+    pub safety_cap: f32,
+
+    pub mobility_offset: [f32; 4],
+    pub knight_mobility: [f32; 2],
+    pub bishop_mobility: [f32; 2],
+    pub rook_mobility: [f32; 2],
+    pub queen_mobility: [f32; 2],
+    pub knight_outpost: [f32; 2],
+    pub bishop_outpost: [f32; 2],
+    pub bishop_pair: [f32; 2],
+    pub rook_open_file: [f32; 2],
+    pub rook_semi_open_file: [f32; 2],
+    pub rook_seventh: [f32; 2],
+    pub trapped_rook: [f32; 2],
+    pub trapped_bishop: [f32; 2],
+    pub trapped_minor: [f32; 2],
+}
+
+impl EvalParams {
+    pub const DEFAULT: EvalParams = EvalParams {
+        piece_values: [1., 3., 4., 5., 9., 100.],
+        heat_map: [
+            [1.0, 1.0, 2.0, 2.5, 2.5, 2.0, 1.0, 1.0],
+            [1.0, 2.0, 3.0, 3.0, 3.0, 3.0, 2.0, 1.0],
+            [2.0, 3.0, 4.0, 3.5, 3.5, 4.0, 3.0, 2.0],
+            [2.5, 3.0, 3.5, 4.0, 4.0, 3.5, 3.0, 2.5],
+            [2.5, 3.0, 3.5, 4.0, 4.0, 3.5, 3.0, 2.5],
+            [2.0, 3.0, 4.0, 3.5, 3.5, 4.0, 3.0, 2.0],
+            [1.0, 2.0, 3.0, 3.0, 3.0, 3.0, 2.0, 1.0],
+            [1.0, 1.0, 2.0, 2.5, 2.5, 2.0, 1.0, 1.0],
+        ],
+        pawn_advance: 0.5,
+        rook_scale: 2.5,
+        queen_scale: 2.5,
+
+        doubled: [-0.5, -1.0],
+        isolated: [-0.5, -0.8],
+        backward: [-0.4, -0.6],
+        connected: [0.15, 0.25],
+        passed_mg: [0., 0.1, 0.2, 0.4, 0.8, 1.4, 2.2, 0.],
+        passed_eg: [0., 0.2, 0.4, 0.8, 1.5, 2.5, 4.0, 0.],
+        free_path: 0.5,
+        king_proximity_own: 0.1,
+        king_proximity_enemy: 0.25,
+
+        shield: [-0.6, 0.4, 0.25, 0.1, 0., 0., 0., 0.],
+        storm: [0., -0.1, -0.5, -0.35, -0.2, -0.1, 0., 0.],
+        open_file: 6.,
+        semi_open_file: 3.,
+        attack_weight: [0., 2., 2., 3., 5., 0.],
+        safety_scale: 0.0015,
+        safety_cap: 6.,
+
+        mobility_offset: [4., 6., 6., 12.],
+        knight_mobility: [0.08, 0.08],
+        bishop_mobility: [0.06, 0.1],
+        rook_mobility: [0.04, 0.1],
+        queen_mobility: [0.02, 0.05],
+        knight_outpost: [0.5, 0.3],
+        bishop_outpost: [0.3, 0.15],
+        bishop_pair: [0.5, 0.7],
+        rook_open_file: [0.4, 0.2],
+        rook_semi_open_file: [0.2, 0.1],
+        rook_seventh: [0.3, 0.5],
+        trapped_rook: [-0.8, -0.3],
+        trapped_bishop: [-1.5, -1.5],
+        trapped_minor: [-0.5, -0.5],
+    };
+
+    pub fn fields(&mut self) -> Vec<(&'static str, &mut [f32])> {
+        vec![
+            ("material.piece_values", &mut self.piece_values[..]),
+            ("material.heat_map", self.heat_map.as_flattened_mut()),
+            ("material.pawn_advance", std::slice::from_mut(&mut self.pawn_advance)),
+            ("material.rook_scale", std::slice::from_mut(&mut self.rook_scale)),
+            ("material.queen_scale", std::slice::from_mut(&mut self.queen_scale)),
+
+            ("pawns.doubled", &mut self.doubled[..]),
+            ("pawns.isolated", &mut self.isolated[..]),
+            ("pawns.backward", &mut self.backward[..]),
+            ("pawns.connected", &mut self.connected[..]),
+            ("pawns.passed_mg", &mut self.passed_mg[..]),
+            ("pawns.passed_eg", &mut self.passed_eg[..]),
+            ("pawns.free_path", std::slice::from_mut(&mut self.free_path)),
+            ("pawns.king_proximity_own", std::slice::from_mut(&mut self.king_proximity_own)),
+            ("pawns.king_proximity_enemy", std::slice::from_mut(&mut self.king_proximity_enemy)),
+
+            ("king_safety.shield", &mut self.shield[..]),
+            ("king_safety.storm", &mut self.storm[..]),
+            ("king_safety.open_file", std::slice::from_mut(&mut self.open_file)),
+            ("king_safety.semi_open_file", std::slice::from_mut(&mut self.semi_open_file)),
+            ("king_safety.attack_weight", &mut self.attack_weight[..]),
+            ("king_safety.safety_scale", std::slice::from_mut(&mut self.safety_scale)),
+            ("king_safety.safety_cap", std::slice::from_mut(&mut self.safety_cap)),
+
+            ("pieces.mobility_offset", &mut self.mobility_offset[..]),
+            ("pieces.knight_mobility", &mut self.knight_mobility[..]),
+            ("pieces.bishop_mobility", &mut self.bishop_mobility[..]),
+            ("pieces.rook_mobility", &mut self.rook_mobility[..]),
+            ("pieces.queen_mobility", &mut self.queen_mobility[..]),
+            ("pieces.knight_outpost", &mut self.knight_outpost[..]),
+            ("pieces.bishop_outpost", &mut self.bishop_outpost[..]),
+            ("pieces.bishop_pair", &mut self.bishop_pair[..]),
+            ("pieces.rook_open_file", &mut self.rook_open_file[..]),
+            ("pieces.rook_semi_open_file", &mut self.rook_semi_open_file[..]),
+            ("pieces.rook_seventh", &mut self.rook_seventh[..]),
+            ("pieces.trapped_rook", &mut self.trapped_rook[..]),
+            ("pieces.trapped_bishop", &mut self.trapped_bishop[..]),
+            ("pieces.trapped_minor", &mut self.trapped_minor[..]),
+        ]
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+        Self::from_toml(&text).map_err(|e| format!("{}:{}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_toml()).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // Reads the flat TOML subset written by `to_toml`: [section] headers and `key = value`,
+    // where a value is a number or a (possibly nested, multi-line) array of numbers.
+    // Keys that are left out keep their compiled-in default.
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let mut params = Self::DEFAULT;
+        let mut seen: Vec<String> = Vec::new();
+
+        let mut section = String::new();
+        let mut pending: Option<(String, String, usize)> = None;
+
+        for (i, raw) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = raw.split('#').next().unwrap_or("").trim();
+
+            if let Some((key, mut value, start)) = pending.take() {
+                value.push(' ');
+                value.push_str(line);
+
+                if balanced(&value) {
+                    params.assign(&key, &value, start, &mut seen)?;
+                } else {
+                    pending = Some((key, value, start));
+                }
+                continue;
+            }
+
+            if line.is_empty() {continue}
+
+            if line.starts_with('[') && !line.contains('=') {
+                if !line.ends_with(']') {
+                    return Err(format!("{}: unterminated section header", line_no));
+                }
+
+                section = line[1..line.len() - 1].trim().to_string();
+
+                if !["material", "pawns", "king_safety", "pieces"].contains(&section.as_str()) {
+                    return Err(format!("{}: unknown section [{}]", line_no, section));
+                }
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("{}: expected `key = value`", line_no));
+            };
+
+            if section.is_empty() {
+                return Err(format!("{}: key `{}` is outside of any section", line_no, key.trim()));
+            }
+
+            let key = format!("{}.{}", section, key.trim());
+            let value = value.trim().to_string();
+
+            if balanced(&value) {
+                params.assign(&key, &value, line_no, &mut seen)?;
+            } else {
+                pending = Some((key, value, line_no));
+            }
+        }
+
+        if let Some((key, _, start)) = pending {
+            return Err(format!("{}: unterminated array for `{}`", start, key));
+        }
+
+        Ok(params)
+    }
+
+    fn assign(&mut self, key: &str, value: &str, line_no: usize, seen: &mut Vec<String>) -> Result<(), String> {
+        if seen.iter().any(|k| k == key) {
+            return Err(format!("{}: `{}` is set more than once", line_no, key));
+        }
+        seen.push(key.to_string());
+
+        let mut values = Vec::new();
+
+        for token in value.split(|c: char| c == '[' || c == ']' || c == ',' || c.is_whitespace()) {
+            if token.is_empty() {continue}
+
+            let v: f32 = token.parse().map_err(|_| format!("{}: `{}` is not a number in `{}`", line_no, token, key))?;
+
+            if !v.is_finite() {
+                return Err(format!("{}: `{}` must be finite in `{}`", line_no, token, key));
+            }
+
+            values.push(v);
+        }
+
+        let mut fields = self.fields();
+
+        let Some((_, slot)) = fields.iter_mut().find(|(name, _)| *name == key) else {
+            return Err(format!("{}: unknown parameter `{}`", line_no, key));
+        };
+
+        if slot.len() != values.len() {
+            return Err(format!("{}: `{}` expects {} value(s), found {}", line_no, key, slot.len(), values.len()));
+        }
+
+        slot.copy_from_slice(&values);
+
+        Ok(())
+    }
+
+    pub fn to_toml(self) -> String {
+        let mut copy = self;
+        let mut out = String::from("# chess evaluation parameters\n");
+        let mut section = "";
+
+        for (name, values) in copy.fields() {
+            let (s, key) = name.split_once('.').unwrap();
+
+            if s != section {
+                section = s;
+                out += &format!("\n[{}]\n", section);
+            }
+
+            let numbers: Vec<String> = values.iter().map(|v| format!("{:?}", v)).collect();
+
+            if key == "heat_map" {
+                out += &format!("{} = [\n", key);
+                for row in numbers.chunks(8) {
+                    out += &format!("    [{}],\n", row.join(", "));
+                }
+                out += "]\n";
+            } else if values.len() == 1 {
+                out += &format!("{} = {}\n", key, numbers[0]);
+            } else {
+                out += &format!("{} = [{}]\n", key, numbers.join(", "));
+            }
+        }
+
+        out
+    }
+}
+
+fn balanced(value: &str) -> bool {
+    value.matches('[').count() <= value.matches(']').count()
+}
+
+static PARAMS: RwLock<EvalParams> = RwLock::new(EvalParams::DEFAULT);
+
+pub fn get() -> RwLockReadGuard<'static, EvalParams> {
+    PARAMS.read().unwrap()
+}
+
+pub fn set(params: EvalParams) {
+    *PARAMS.write().unwrap() = params;
+}
@@ -0,0 +1,239 @@
+use std::cell::RefCell;
+
+use crate::params::EvalParams;
+use crate::trace::{Term, Trace};
+use crate::{Kind, Piece};
+
+const TABLE_SIZE: usize = 1 << 14;
+
+const fn keys() -> [[u64; 64]; 2] {
+    let mut keys = [[0; 64]; 2];
+    let mut s: u64 = 0x9E37_79B9_7F4A_7C15;
+
+    let mut c = 0;
+    while c < 2 {
+        let mut i = 0;
+        while i < 64 {
+            s ^= s << 13;
+            s ^= s >> 7;
+            s ^= s << 17;
+            keys[c][i] = s;
+            i += 1;
+        }
+        c += 1;
+    }
+
+    keys
+}
+
+const PAWN_KEYS: [[u64; 64]; 2] = keys();
+
+#[derive(Copy, Clone, Default)]
+struct Entry {
+    key: u64,
+    structure: [(f32, f32); 2],
+    passed_score: [(f32, f32); 2],
+    passed: [u64; 2],
+}
+
+thread_local! {
+    static TABLE: RefCell<Vec<Entry>> = RefCell::new(vec![Entry::default(); TABLE_SIZE]);
+}
+
+pub fn pawn_key(board: &[[Option<Piece>; 8]; 8]) -> u64 {
+    let mut key = 0;
+
+    for y in 0..8 {
+        for x in 0..8 {
+            if let Some(piece) = board[y][x] {
+                if piece.kind == Kind::Pawn {
+                    key ^= PAWN_KEYS[!piece.white as usize][y * 8 + x];
+                }
+            }
+        }
+    }
+
+    key
+}
+
+pub fn evaluate(board: &[[Option<Piece>; 8]; 8], params: &EvalParams, trace: &mut Trace) {
+    let entry = if trace.detailed {
+        structure(board, params, Some(&mut trace.squares))
+    } else {
+        let key = pawn_key(board) ^ salt(params);
+
+        TABLE.with(|table| {
+            let mut table = table.borrow_mut();
+            let slot = &mut table[key as usize % TABLE_SIZE];
+
+            if slot.key != key {
+                *slot = structure(board, params, None);
+                slot.key = key;
+            }
+
+            *slot
+        })
+    };
+
+    let kings = kings(board);
+
+    for (c, white) in [(0, true), (1, false)] {
+        let fwd: isize = if white {-1} else {1};
+
+        trace.add(Term::Pawns, white, entry.structure[c].0, entry.structure[c].1);
+        trace.add(Term::PassedPawns, white, entry.passed_score[c].0, entry.passed_score[c].1);
+
+        for sq in 0..64 {
+            if entry.passed[c] & (1 << sq) == 0 {continue}
+
+            let (x, y) = (sq % 8, sq / 8);
+            let r = relative_rank(y, white);
+            let stop = (x as isize, y as isize + fwd);
+
+            let mut free = true;
+            let mut yy = stop.1;
+            while (0..8).contains(&yy) {
+                if board[yy as usize][x].is_some() {free = false}
+                yy += fwd;
+            }
+
+            if free {
+                trace.add_at(Term::PassedPawns, white, params.passed_mg[r] * params.free_path, params.passed_eg[r] * params.free_path, x, y);
+            }
+
+            if let (Some(own), Some(enemy)) = (kings[c], kings[1 - c]) {
+                let w = r as f32;
+                let eg = w * (params.king_proximity_enemy * distance(enemy, stop) as f32 - params.king_proximity_own * distance(own, stop) as f32);
+                trace.add_at(Term::PassedPawns, white, 0., eg, x, y);
+            }
+        }
+    }
+}
+
+// Mixes the pawn weights into the key so that entries cached under other parameters never hit.
+fn salt(params: &EvalParams) -> u64 {
+    let weights = [params.doubled, params.isolated, params.backward, params.connected];
+
+    weights.iter().flatten()
+        .chain(params.passed_mg.iter())
+        .chain(params.passed_eg.iter())
+        .fold(0, |h: u64, w| (h ^ w.to_bits() as u64).wrapping_mul(0x100_0000_01B3))
+}
+
+fn structure(board: &[[Option<Piece>; 8]; 8], params: &EvalParams, mut squares: Option<&mut [[(f32, f32); 8]; 8]>) -> Entry {
+    let mut e = Entry::default();
+
+    for y in 0..8 {
+        for x in 0..8 {
+            let Some(piece) = board[y][x] else {continue};
+            if piece.kind != Kind::Pawn {continue}
+
+            let white = piece.white;
+            let c = !white as usize;
+            let fwd: isize = if white {-1} else {1};
+            let (xi, yi) = (x as isize, y as isize);
+            let r = relative_rank(y, white);
+
+            let mut doubled = false;
+            let mut passed = true;
+            let mut isolated = true;
+            let mut behind_neighbour = false;
+
+            for yy in 0..8 {
+                let ahead = relative_rank(yy, white) > r;
+
+                if ahead && pawn_at(board, xi, yy as isize, white) {doubled = true}
+
+                for xx in [xi - 1, xi + 1] {
+                    if pawn_at(board, xx, yy as isize, white) {
+                        isolated = false;
+                        if !ahead {behind_neighbour = true}
+                    }
+                }
+
+                if ahead {
+                    for xx in [xi - 1, xi, xi + 1] {
+                        if pawn_at(board, xx, yy as isize, !white) {passed = false}
+                    }
+                }
+            }
+
+            let supported = pawn_at(board, xi - 1, yi - fwd, white) || pawn_at(board, xi + 1, yi - fwd, white);
+            let phalanx = pawn_at(board, xi - 1, yi, white) || pawn_at(board, xi + 1, yi, white);
+
+            let stop_attacked = pawn_at(board, xi - 1, yi + 2 * fwd, !white) || pawn_at(board, xi + 1, yi + 2 * fwd, !white);
+            let backward = !isolated && !behind_neighbour && stop_attacked;
+
+            let mut s = (0., 0.);
+
+            if doubled {
+                s.0 += params.doubled[0];
+                s.1 += params.doubled[1];
+            }
+            if isolated {
+                s.0 += params.isolated[0];
+                s.1 += params.isolated[1];
+            }
+            if backward {
+                s.0 += params.backward[0];
+                s.1 += params.backward[1];
+            }
+            if supported || phalanx {
+                s.0 += params.connected[0] * r as f32;
+                s.1 += params.connected[1] * r as f32;
+            }
+
+            e.structure[c].0 += s.0;
+            e.structure[c].1 += s.1;
+
+            if passed && !doubled {
+                e.passed_score[c].0 += params.passed_mg[r];
+                e.passed_score[c].1 += params.passed_eg[r];
+                e.passed[c] |= 1 << (y * 8 + x);
+
+                s.0 += params.passed_mg[r];
+                s.1 += params.passed_eg[r];
+            }
+
+            if let Some(squares) = squares.as_deref_mut() {
+                squares[y][x].0 += s.0;
+                squares[y][x].1 += s.1;
+            }
+        }
+    }
+
+    e
+}
+
+fn relative_rank(y: usize, white: bool) -> usize {
+    if white {7 - y} else {y}
+}
+
+fn pawn_at(board: &[[Option<Piece>; 8]; 8], x: isize, y: isize, white: bool) -> bool {
+    if !(0..8).contains(&x) || !(0..8).contains(&y) {return false}
+
+    match board[y as usize][x as usize] {
+        Some(p) => p.kind == Kind::Pawn && p.white == white,
+        None => false,
+    }
+}
+
+fn kings(board: &[[Option<Piece>; 8]; 8]) -> [Option<(isize, isize)>; 2] {
+    let mut kings = [None; 2];
+
+    for (y, row) in board.iter().enumerate() {
+        for (x, square) in row.iter().enumerate() {
+            if let Some(piece) = square {
+                if piece.kind == Kind::King {
+                    kings[!piece.white as usize] = Some((x as isize, y as isize));
+                }
+            }
+        }
+    }
+
+    kings
+}
+
+fn distance(a: (isize, isize), b: (isize, isize)) -> isize {
+    (a.0 - b.0).abs().max((a.1 - b.1).abs())
+}
@@ -0,0 +1,154 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+// A move as written in the movetext, with where it was found so that errors can point at it.
+pub struct Ply {
+    pub san: String,
+    pub line: usize,
+    pub col: usize,
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+    // Alternatives to this move, each played from the position before it.
+    pub variations: Vec<Vec<Ply>>,
+}
+
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<Ply>,
+    pub result: String,
+}
+
+impl Game {
+    fn new() -> Self {
+        Game { tags: Vec::new(), moves: Vec::new(), result: "*".to_string() }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+struct Reader<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    col: usize,
+}
+
+impl Reader<'_> {
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    // Everything up to `end`, which is consumed; None when the text runs out first.
+    fn until(&mut self, end: char) -> Option<String> {
+        let mut s = String::new();
+        while let Some(c) = self.next() {
+            if c == end {return Some(s)}
+            s.push(c);
+        }
+        None
+    }
+}
+
+// Splits `text` into games. Errors are prefixed with the line and column they were found at.
+pub fn parse(text: &str) -> Result<Vec<Game>, String> {
+    let mut r = Reader { chars: text.chars().peekable(), line: 1, col: 1 };
+    let mut games = Vec::new();
+    let mut game = Game::new();
+
+    // The main line at the bottom, with the variations currently open above it.
+    let mut lines: Vec<Vec<Ply>> = vec![Vec::new()];
+
+    let finish = |games: &mut Vec<Game>, game: &mut Game, main: &mut Vec<Ply>| {
+        game.moves = std::mem::take(main);
+        if !game.tags.is_empty() || !game.moves.is_empty() {
+            games.push(std::mem::replace(game, Game::new()));
+        }
+    };
+
+    loop {
+        let (line, col) = (r.line, r.col);
+        let Some(c) = r.next() else {break};
+        let at = |e: &str| format!("{}:{}: {}", line, col, e);
+
+        match c {
+            '[' if lines.len() == 1 => {
+                if !lines[0].is_empty() {finish(&mut games, &mut game, &mut lines[0])}
+
+                let tag = r.until(']').ok_or(at("unterminated tag"))?;
+                let (name, value) = tag.trim().split_once(' ').ok_or(at(&format!("invalid tag [{}]", tag)))?;
+                game.tags.push((name.to_string(), value.trim().trim_matches('"').to_string()));
+            },
+            '{' => {
+                let comment = r.until('}').ok_or(at("unterminated comment"))?;
+                let comment = comment.split_whitespace().collect::<Vec<_>>().join(" ");
+
+                // Comments before the first move of a line have nothing to belong to.
+                if let Some(ply) = lines.last_mut().unwrap().last_mut() {
+                    ply.comment = Some(match ply.comment.take() {
+                        Some(c) => c + " " + &comment,
+                        None => comment,
+                    });
+                }
+            },
+            ';' => {r.until('\n');},
+            '%' if col == 1 => {r.until('\n');},
+            '(' => {
+                if lines.last().unwrap().is_empty() {return Err(at("variation without a move to replace"))}
+                lines.push(Vec::new());
+            },
+            ')' => {
+                if lines.len() == 1 {return Err(at("unmatched )"))}
+                let variation = lines.pop().unwrap();
+                lines.last_mut().unwrap().last_mut().unwrap().variations.push(variation);
+            },
+            c if c.is_whitespace() => {},
+            c => {
+                let mut token = c.to_string();
+                while let Some(&c) = r.chars.peek() {
+                    if c.is_whitespace() || "{}();[".contains(c) {break}
+                    token.push(c);
+                    r.next();
+                }
+
+                if let Some(nag) = token.strip_prefix('$') {
+                    let nag = nag.parse().map_err(|_| at(&format!("invalid NAG {}", token)))?;
+                    let ply = lines.last_mut().unwrap().last_mut().ok_or(at(&format!("{} without a move", token)))?;
+                    ply.nags.push(nag);
+                    continue;
+                }
+
+                match token.as_str() {
+                    "1-0" | "0-1" | "1/2-1/2" | "*" => {
+                        if lines.len() > 1 {return Err(at(&format!("result {} inside a variation", token)))}
+                        game.result = token;
+                        finish(&mut games, &mut game, &mut lines[0]);
+                    },
+                    _ => {
+                        let san = match token.find('.') {
+                            Some(i) if token[..i].chars().all(|c| c.is_ascii_digit()) => token[i..].trim_start_matches('.'),
+                            _ => &token,
+                        };
+
+                        if !san.is_empty() {
+                            let col = col + token.len() - san.len();
+                            lines.last_mut().unwrap().push(Ply { san: san.to_string(), line, col, nags: Vec::new(), comment: None, variations: Vec::new() });
+                        }
+                    },
+                }
+            },
+        }
+    }
+
+    if lines.len() > 1 {return Err(format!("{}:{}: unterminated variation", r.line, r.col))}
+
+    finish(&mut games, &mut game, &mut lines[0]);
+    Ok(games)
+}
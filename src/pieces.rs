@@ -0,0 +1,113 @@
+use crate::params::EvalParams;
+use crate::trace::{Term, Trace};
+use crate::{attacks, Kind, Piece};
+
+pub fn evaluate(board: &[[Option<Piece>; 8]; 8], params: &EvalParams, trace: &mut Trace) {
+    let mut bishops = [0; 2];
+
+    for y in 0..8 {
+        for x in 0..8 {
+            let Some(piece) = board[y][x] else {continue};
+            let white = piece.white;
+            let r = if white {7 - y} else {y};
+
+            let safe = attacks(board, x, y).iter()
+                .filter(|&&(tx, ty)| !matches!(board[ty][tx], Some(p) if p.white == white))
+                .filter(|&&(tx, ty)| !pawn_attacked(board, tx, ty, !white))
+                .count() as f32;
+
+            let mut add = |term: Term, weight: [f32; 2], n: f32| {
+                trace.add_at(term, white, weight[0] * n, weight[1] * n, x, y);
+            };
+
+            match piece.kind {
+                Kind::Knight => {
+                    add(Term::Mobility, params.knight_mobility, safe - params.mobility_offset[0]);
+
+                    if outpost(board, x, y, white) {add(Term::Outposts, params.knight_outpost, 1.)}
+                    if r >= 2 && safe <= 1. {add(Term::Trapped, params.trapped_minor, 1.)}
+                },
+                Kind::Bishop => {
+                    add(Term::Mobility, params.bishop_mobility, safe - params.mobility_offset[1]);
+                    bishops[!white as usize] += 1;
+
+                    if outpost(board, x, y, white) {add(Term::Outposts, params.bishop_outpost, 1.)}
+
+                    let fwd: isize = if white {1} else {-1};
+                    if r == 6 && (x == 0 || x == 7) {
+                        let bx = if x == 0 {1} else {6};
+                        let by = (y as isize + fwd) as usize;
+                        if matches!(board[by][bx], Some(p) if p.kind == Kind::Pawn && p.white != white) {
+                            add(Term::Trapped, params.trapped_bishop, 1.);
+                        }
+                    } else if r >= 2 && safe <= 1. {
+                        add(Term::Trapped, params.trapped_minor, 1.);
+                    }
+                },
+                Kind::Rook => {
+                    add(Term::Mobility, params.rook_mobility, safe - params.mobility_offset[2]);
+
+                    let own = (0..8).any(|yy| matches!(board[yy][x], Some(p) if p.kind == Kind::Pawn && p.white == white));
+                    let enemy = (0..8).any(|yy| matches!(board[yy][x], Some(p) if p.kind == Kind::Pawn && p.white != white));
+
+                    if !own && !enemy {add(Term::Rooks, params.rook_open_file, 1.)}
+                    else if !own {add(Term::Rooks, params.rook_semi_open_file, 1.)}
+
+                    if r == 6 {
+                        let back = if white {0} else {7};
+                        let seventh = if white {1} else {6};
+                        let king = (0..8).any(|xx| matches!(board[back][xx], Some(p) if p.kind == Kind::King && p.white != white));
+                        let pawns = (0..8).any(|xx| matches!(board[seventh][xx], Some(p) if p.kind == Kind::Pawn && p.white != white));
+
+                        if king || pawns {add(Term::Rooks, params.rook_seventh, 1.)}
+                    }
+
+                    if r == 0 && safe <= 3. {
+                        let king = (0..8).find(|&xx| matches!(board[y][xx], Some(p) if p.kind == Kind::King && p.white == white));
+
+                        if let Some(kx) = king {
+                            if (kx < 4) == (x < kx) && kx != 4 {add(Term::Trapped, params.trapped_rook, 1.)}
+                        }
+                    }
+                },
+                Kind::Queen => {
+                    add(Term::Mobility, params.queen_mobility, safe - params.mobility_offset[3]);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    for (c, white) in [(0, true), (1, false)] {
+        if bishops[c] >= 2 {trace.add(Term::BishopPair, white, params.bishop_pair[0], params.bishop_pair[1])}
+    }
+}
+
+fn pawn_attacked(board: &[[Option<Piece>; 8]; 8], x: usize, y: usize, by_white: bool) -> bool {
+    let py = if by_white {y as isize + 1} else {y as isize - 1};
+    if !(0..8).contains(&py) {return false}
+
+    [x as isize - 1, x as isize + 1].iter().any(|&px| {
+        (0..8).contains(&px) && matches!(board[py as usize][px as usize], Some(p) if p.kind == Kind::Pawn && p.white == by_white)
+    })
+}
+
+fn outpost(board: &[[Option<Piece>; 8]; 8], x: usize, y: usize, white: bool) -> bool {
+    let r = if white {7 - y} else {y};
+    if !(3..=5).contains(&r) {return false}
+
+    if !pawn_attacked(board, x, y, white) {return false}
+
+    for (yy, row) in board.iter().enumerate() {
+        let ahead = if white {yy < y} else {yy > y};
+        if !ahead {continue}
+
+        for xx in [x as isize - 1, x as isize + 1] {
+            if (0..8).contains(&xx) && matches!(row[xx as usize], Some(p) if p.kind == Kind::Pawn && p.white != white) {
+                return false;
+            }
+        }
+    }
+
+    true
+}
@@ -0,0 +1,310 @@
+use crate::{valid_moves, Kind, Piece};
+
+// From square, to square, and the piece a pawn promotes to (None for every other move).
+pub type Move = (usize, usize, usize, usize, Option<Kind>);
+
+#[derive(Copy, Clone, Debug)]
+pub struct Position {
+    pub board: [[Option<Piece>; 8]; 8],
+    pub white: bool,
+    // White king side, white queen side, black king side, black queen side.
+    pub castling: [bool; 4],
+    pub ep: Option<(usize, usize)>,
+    pub halfmove: u32,
+    pub fullmove: u32,
+}
+
+impl Position {
+    pub fn start() -> Self {
+        crate::fen::parse(crate::fen::START).unwrap()
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let wmk = !self.castling[0] && !self.castling[1];
+        let bmk = !self.castling[2] && !self.castling[3];
+
+        let mut moves: Vec<Move> = valid_moves(self.board, wmk, bmk).into_iter()
+            .filter(|m| matches!(self.board[m.1][m.0], Some(p) if p.white == self.white))
+            .filter(|m| !matches!(self.board[m.3][m.2], Some(p) if p.kind == Kind::King))
+            .map(|(x1, y1, x2, y2)| (x1, y1, x2, y2, None))
+            .collect();
+
+        if let Some((ex, ey)) = self.ep {
+            let py = if self.white {ey + 1} else {ey - 1};
+
+            for px in [ex as isize - 1, ex as isize + 1] {
+                if !(0..8).contains(&px) {continue}
+
+                if matches!(self.board[py][px as usize], Some(p) if p.kind == Kind::Pawn && p.white == self.white) {
+                    moves.push((px as usize, py, ex, ey, None));
+                }
+            }
+        }
+
+        moves.retain(|&m| {
+            if self.is_castle(m) && !self.castle_allowed(m) {return false}
+
+            !self.make(m).in_check(self.white)
+        });
+
+        moves.into_iter().flat_map(|m| {
+            let kinds = if self.is_promotion(m) {vec![Kind::Queen, Kind::Rook, Kind::Bishop, Kind::Knight]} else {Vec::new()};
+            if kinds.is_empty() {vec![m]} else {kinds.into_iter().map(|k| (m.0, m.1, m.2, m.3, Some(k))).collect()}
+        }).collect()
+    }
+
+    pub fn is_castle(&self, m: Move) -> bool {
+        matches!(self.board[m.1][m.0], Some(p) if p.kind == Kind::King) && m.0.abs_diff(m.2) == 2
+    }
+
+    fn castle_allowed(&self, m: Move) -> bool {
+        let (x1, y, x2, _, _) = m;
+        let right = if self.white {0} else {2} + if x2 > x1 {0} else {1};
+        let rook_x = if x2 > x1 {7} else {0};
+
+        if !self.castling[right] {return false}
+        if !matches!(self.board[y][rook_x], Some(p) if p.kind == Kind::Rook && p.white == self.white) {return false}
+
+        let step = if x2 > x1 {1} else {-1};
+        let through = (x1 as isize + step) as usize;
+
+        !self.in_check(self.white) && !attacked(&self.board, through, y, !self.white)
+    }
+
+    pub fn is_capture(&self, m: Move) -> bool {
+        self.board[m.3][m.2].is_some() || self.is_en_passant(m)
+    }
+
+    pub fn is_en_passant(&self, m: Move) -> bool {
+        matches!(self.board[m.1][m.0], Some(p) if p.kind == Kind::Pawn) && m.0 != m.2 && self.board[m.3][m.2].is_none()
+    }
+
+    pub fn is_promotion(&self, m: Move) -> bool {
+        matches!(self.board[m.1][m.0], Some(p) if p.kind == Kind::Pawn) && (m.3 == 0 || m.3 == 7)
+    }
+
+    pub fn make(&self, m: Move) -> Position {
+        let (x1, y1, x2, y2, promotion) = m;
+        let mut next = *self;
+        let Some(piece) = self.board[y1][x1] else {return next};
+
+        let capture = self.is_capture(m);
+
+        if self.is_en_passant(m) {
+            next.board[y1][x2] = None;
+        }
+
+        if self.is_castle(m) {
+            let (from, to) = if x2 > x1 {(7, 5)} else {(0, 3)};
+            next.board[y1][to] = next.board[y1][from];
+            next.board[y1][from] = None;
+        }
+
+        next.board[y2][x2] = Some(piece);
+        next.board[y1][x1] = None;
+
+        if self.is_promotion(m) {
+            next.board[y2][x2] = Some(Piece { kind: promotion.unwrap_or(Kind::Queen), white: piece.white });
+        }
+
+        if piece.kind == Kind::King {
+            let c = if piece.white {0} else {2};
+            next.castling[c] = false;
+            next.castling[c + 1] = false;
+        }
+
+        for (i, corner) in [(7, 7), (0, 7), (7, 0), (0, 0)].iter().enumerate() {
+            if (x1, y1) == *corner || (x2, y2) == *corner {next.castling[i] = false}
+        }
+
+        next.ep = None;
+        if piece.kind == Kind::Pawn && y1.abs_diff(y2) == 2 {
+            next.ep = Some((x1, (y1 + y2) / 2));
+        }
+
+        next.halfmove = if capture || piece.kind == Kind::Pawn {0} else {self.halfmove + 1};
+        if !self.white {next.fullmove += 1}
+        next.white = !self.white;
+
+        next
+    }
+
+    pub fn king(&self, white: bool) -> Option<(usize, usize)> {
+        for (y, row) in self.board.iter().enumerate() {
+            for (x, square) in row.iter().enumerate() {
+                if matches!(square, Some(p) if p.kind == Kind::King && p.white == white) {
+                    return Some((x, y));
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn in_check(&self, white: bool) -> bool {
+        match self.king(white) {
+            Some((x, y)) => attacked(&self.board, x, y, !white),
+            None => false,
+        }
+    }
+
+    // Whether a set up position can be played from: one king each, no pawns on the back ranks,
+    // the side that just moved not in check, castling rights backed by the king and rook on their
+    // squares, and an en passant square behind a pawn that could just have moved two squares.
+    pub fn validate(&self) -> Result<(), String> {
+        for (white, side) in [(true, "white"), (false, "black")] {
+            let kings = self.board.iter().flatten().flatten().filter(|p| p.kind == Kind::King && p.white == white).count();
+            if kings != 1 {return Err(format!("{} has {} kings", side, kings))}
+        }
+
+        if self.board[0].iter().chain(&self.board[7]).flatten().any(|p| p.kind == Kind::Pawn) {
+            return Err("pawn on the first or last rank".to_string());
+        }
+
+        if self.in_check(!self.white) {
+            return Err(format!("{} is in check but not to move", if self.white {"black"} else {"white"}));
+        }
+
+        let is = |x: usize, y: usize, kind: Kind, white: bool| self.board[y][x].is_some_and(|p| p.kind == kind && p.white == white);
+        for (i, (rook, y, white)) in [(7, 7, true), (0, 7, true), (7, 0, false), (0, 0, false)].into_iter().enumerate() {
+            if self.castling[i] && !(is(4, y, Kind::King, white) && is(rook, y, Kind::Rook, white)) {
+                return Err(format!("castling right {} without the king and rook at home", "KQkq".as_bytes()[i] as char));
+            }
+        }
+
+        if let Some(sq) = self.ep.filter(|&sq| !self.en_passant_possible(sq)) {
+            return Err(format!("no pawn can be taken en passant on {}", crate::fen::square_name(sq)));
+        }
+
+        Ok(())
+    }
+
+    // Whether `(x, y)` is behind a pawn of the side that just moved, with the square that pawn
+    // would have come from empty.
+    pub fn en_passant_possible(&self, (x, y): (usize, usize)) -> bool {
+        let (target, pawn, from) = if self.white {(2, 3, 1)} else {(5, 4, 6)};
+        y == target && self.board[y][x].is_none() && self.board[from][x].is_none()
+            && self.board[pawn][x].is_some_and(|p| p.kind == Kind::Pawn && p.white != self.white)
+    }
+
+    pub fn insufficient_material(&self) -> bool {
+        let mut minors = 0;
+
+        for piece in self.board.iter().flatten().flatten() {
+            match piece.kind {
+                Kind::King => {},
+                Kind::Knight | Kind::Bishop => minors += 1,
+                _ => return false,
+            }
+        }
+
+        minors <= 1
+    }
+
+    // Whether `white` has more than a lone king or a king and one minor piece, and so could mate.
+    pub fn mating_material(&self, white: bool) -> bool {
+        let mut minors = 0;
+
+        for piece in self.board.iter().flatten().flatten().filter(|p| p.white == white) {
+            match piece.kind {
+                Kind::King => {},
+                Kind::Knight | Kind::Bishop => minors += 1,
+                _ => return true,
+            }
+        }
+
+        minors >= 2
+    }
+
+    // Identifies the position for repetition checks: placement, side to move, castling and en passant.
+    pub fn hash(&self) -> u64 {
+        let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut mix = |v: u64| h = (h ^ v).wrapping_mul(0x100_0000_01B3);
+
+        for (i, square) in self.board.iter().flatten().enumerate() {
+            if let Some(p) = square {
+                mix((i as u64) << 8 | (p.kind.index() as u64) << 1 | p.white as u64);
+            }
+        }
+
+        mix(self.white as u64);
+        mix(self.castling.iter().fold(0, |a, &c| a << 1 | c as u64) | 0x100);
+        mix(self.ep.map_or(0xFFFF, |(x, y)| (y * 8 + x) as u64));
+
+        h
+    }
+}
+
+pub fn attacked(board: &[[Option<Piece>; 8]; 8], x: usize, y: usize, by_white: bool) -> bool {
+    let at = |dx: isize, dy: isize| -> Option<Piece> {
+        let (tx, ty) = (x as isize + dx, y as isize + dy);
+        if !(0..8).contains(&tx) || !(0..8).contains(&ty) {return None}
+        board[ty as usize][tx as usize].filter(|p| p.white == by_white)
+    };
+
+    let pawn_dy = if by_white {1} else {-1};
+    for dx in [-1, 1] {
+        if matches!(at(dx, pawn_dy), Some(p) if p.kind == Kind::Pawn) {return true}
+    }
+
+    for (dx, dy) in [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)] {
+        if matches!(at(dx, dy), Some(p) if p.kind == Kind::Knight) {return true}
+    }
+
+    for (dx, dy) in [(1, 1), (1, -1), (-1, 1), (-1, -1), (1, 0), (-1, 0), (0, 1), (0, -1)] {
+        if matches!(at(dx, dy), Some(p) if p.kind == Kind::King) {return true}
+
+        let diagonal = dx != 0 && dy != 0;
+        let (mut tx, mut ty) = (x as isize + dx, y as isize + dy);
+
+        while (0..8).contains(&tx) && (0..8).contains(&ty) {
+            if let Some(p) = board[ty as usize][tx as usize] {
+                if p.white == by_white && (p.kind == Kind::Queen || p.kind == if diagonal {Kind::Bishop} else {Kind::Rook}) {
+                    return true;
+                }
+                break;
+            }
+
+            tx += dx;
+            ty += dy;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fen, san};
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+    const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+
+    fn perft(pos: &Position, depth: u32) -> u64 {
+        let moves = pos.legal_moves();
+        if depth == 1 {return moves.len() as u64}
+
+        moves.iter().map(|&m| perft(&pos.make(m), depth - 1)).sum()
+    }
+
+    #[test]
+    fn perft_counts() {
+        assert_eq!(perft(&Position::start(), 4), 197281);
+        assert_eq!(perft(&fen::parse(KIWIPETE).unwrap(), 3), 97862);
+        assert_eq!(perft(&fen::parse(POSITION_5).unwrap(), 3), 62379);
+    }
+
+    #[test]
+    fn san_round_trip() {
+        for f in [KIWIPETE, POSITION_4] {
+            let pos = fen::parse(f).unwrap();
+
+            for m in pos.legal_moves() {
+                let text = san::format(&pos, m, &pos.make(m));
+                assert_eq!(san::parse(&pos, &text), Ok(m), "{} in {}", text, f);
+            }
+        }
+    }
+}
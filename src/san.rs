@@ -0,0 +1,175 @@
+use crate::fen::{self, parse_square, square_name};
+use crate::position::{Move, Position};
+use crate::{Kind, Piece};
+
+// Accepts standard algebraic notation, tolerating missing or superfluous check marks,
+// annotation suffixes, "0-0" castling, promotions written without "=" or in lower case, and
+// long algebraic or coordinate moves such as "Ng1-f3" and "e7e8q".
+pub fn parse(pos: &Position, san: &str) -> Result<Move, String> {
+    let s = strip_suffixes(san);
+
+    if s == "O-O" || s == "0-0" || s == "O-O-O" || s == "0-0-0" {
+        let (x, y) = pos.king(pos.white).ok_or("no king")?;
+        let x2 = if s.len() == 3 {x + 2} else {x.wrapping_sub(2)};
+
+        return pos.legal_moves().into_iter()
+            .find(|&m| (m.0, m.1, m.2, m.3) == (x, y, x2, y) && pos.is_castle(m))
+            .ok_or(format!("illegal move {}", san));
+    }
+
+    let mut chars: Vec<char> = s.chars().collect();
+
+    let kind = match chars.first() {
+        Some('N') => Kind::Knight,
+        Some('B') => Kind::Bishop,
+        Some('R') => Kind::Rook,
+        Some('Q') => Kind::Queen,
+        Some('K') => Kind::King,
+        _ => Kind::Pawn,
+    };
+    if kind != Kind::Pawn {chars.remove(0);}
+
+    let promotion = if kind == Kind::Pawn {promotion_letter(s)} else {None};
+    if let Some(i) = chars.iter().position(|&c| c == '=') {
+        chars.truncate(i);
+    } else if promotion.is_some() {
+        chars.pop();
+    }
+
+    chars.retain(|&c| c != 'x' && c != '-' && c != ':');
+    if chars.len() < 2 {return Err(format!("invalid move {}", san))}
+
+    let target: String = chars[chars.len() - 2..].iter().collect();
+    let (x2, y2) = parse_square(&target).ok_or(format!("invalid move {}", san))?;
+
+    let mut from_x = None;
+    let mut from_y = None;
+    for &c in &chars[..chars.len() - 2] {
+        match c {
+            'a'..='h' => from_x = Some(c as usize - 'a' as usize),
+            '1'..='8' => from_y = Some(8 - (c as usize - '0' as usize)),
+            _ => return Err(format!("invalid move {}", san)),
+        }
+    }
+
+    // A full origin square without a piece letter is a coordinate move of any piece, castling included.
+    let coordinate = kind == Kind::Pawn && from_x.is_some() && from_y.is_some();
+
+    let candidates: Vec<Move> = pos.legal_moves().into_iter()
+        .filter(|&m| (m.2, m.3) == (x2, y2))
+        .filter(|&m| coordinate || matches!(pos.board[m.1][m.0], Some(p) if p.kind == kind))
+        .filter(|&m| from_x.is_none_or(|x| m.0 == x) && from_y.is_none_or(|y| m.1 == y))
+        .filter(|&m| coordinate || !pos.is_castle(m))
+        .filter(|&m| m.4.is_none() || m.4 == Some(promotion.unwrap_or(Kind::Queen)))
+        .collect();
+
+    match candidates.len() {
+        0 => Err(format!("illegal move {}", san)),
+        1 => Ok(candidates[0]),
+        _ => Err(format!("ambiguous move {}", san)),
+    }
+}
+
+fn strip_suffixes(san: &str) -> &str {
+    let s = san.trim().trim_end_matches(['+', '#', '!', '?']);
+    s.strip_suffix("e.p.").or(s.strip_suffix("ep")).unwrap_or(s).trim_end_matches(['+', '#'])
+}
+
+// A trailing promotion piece, upper or lower case, after "=" or directly after the last rank.
+fn promotion_letter(s: &str) -> Option<Kind> {
+    let mut chars = s.chars().rev();
+    let letter = chars.next()?;
+    if !matches!(chars.next(), Some('1' | '8' | '=')) {return None}
+
+    match letter.to_ascii_uppercase() {
+        'N' => Some(Kind::Knight),
+        'B' => Some(Kind::Bishop),
+        'R' => Some(Kind::Rook),
+        'Q' => Some(Kind::Queen),
+        _ => None,
+    }
+}
+
+// `next` is the position after `m`; it supplies the check and mate suffix.
+pub fn format(pos: &Position, m: Move, next: &Position) -> String {
+    let Some(piece) = pos.board[m.1][m.0] else {return String::new()};
+    let letter = |kind| fen::piece_char(Piece { kind, white: true });
+
+    let mut s = String::new();
+
+    if pos.is_castle(m) {
+        s += if m.2 > m.0 {"O-O"} else {"O-O-O"};
+    } else if piece.kind == Kind::Pawn {
+        if pos.is_capture(m) {
+            s.push((b'a' + m.0 as u8) as char);
+            s.push('x');
+        }
+        s += &square_name((m.2, m.3));
+
+        if let Some(kind) = m.4 {
+            s.push('=');
+            s.push(letter(kind));
+        }
+    } else {
+        s.push(letter(piece.kind));
+
+        let others: Vec<Move> = pos.legal_moves().into_iter()
+            .filter(|&o| o != m && (o.2, o.3) == (m.2, m.3))
+            .filter(|&o| matches!(pos.board[o.1][o.0], Some(p) if p.kind == piece.kind))
+            .collect();
+
+        if !others.is_empty() {
+            let file = (b'a' + m.0 as u8) as char;
+            let rank = (b'8' - m.1 as u8) as char;
+
+            if others.iter().all(|o| o.0 != m.0) {
+                s.push(file);
+            } else if others.iter().all(|o| o.1 != m.1) {
+                s.push(rank);
+            } else {
+                s.push(file);
+                s.push(rank);
+            }
+        }
+
+        if pos.is_capture(m) {s.push('x')}
+        s += &square_name((m.2, m.3));
+    }
+
+    if next.in_check(next.white) {
+        s.push(if next.legal_moves().is_empty() {'#'} else {'+'});
+    }
+
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(f: &str, m: Move, expected: &str, inputs: &[&str]) {
+        let pos = fen::parse(f).unwrap();
+        assert_eq!(format(&pos, m, &pos.make(m)), expected, "{}", f);
+
+        for &s in [expected].iter().chain(inputs) {
+            assert_eq!(parse(&pos, s), Ok(m), "{} in {}", s, f);
+        }
+    }
+
+    #[test]
+    fn disambiguation() {
+        check("rnbqkb1r/ppp1pppp/5n2/3p4/3P4/5N2/PPP1PPPP/RNBQKB1R b KQkq - 1 2", (1, 0, 3, 1, None), "Nbd7", &["Nb8d7", "b8d7"]);
+        check("7k/8/8/8/8/4R3/8/4RK2 w - - 0 1", (4, 7, 4, 6, None), "R1e2", &["Re1e2", "Re1-e2"]);
+        assert!(parse(&fen::parse("7k/8/8/8/8/4R3/8/4RK2 w - - 0 1").unwrap(), "Re2").is_err());
+    }
+
+    #[test]
+    fn promotion_castling_and_mate() {
+        check("8/4P2k/8/8/8/8/8/K7 w - - 0 1", (4, 1, 4, 0, Some(Kind::Queen)), "e8=Q", &["e8Q", "e8q", "e7e8q"]);
+        check("8/4P2k/8/8/8/8/8/K7 w - - 0 1", (4, 1, 4, 0, Some(Kind::Knight)), "e8=N", &["e8N"]);
+        check("r3k3/8/8/8/8/8/8/4K3 b q - 0 1", (4, 0, 2, 0, None), "O-O-O", &["0-0-0", "e8c8"]);
+        check("4k3/8/8/8/8/8/8/4K2R w K - 0 1", (4, 7, 6, 7, None), "O-O", &["0-0", "O-O+"]);
+        check("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", (0, 7, 0, 0, None), "Ra8#", &["Ra8", "Ra8+", "Ra1-a8#"]);
+        check("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", (0, 7, 0, 0, None), "Ra8+", &["Ra8", "Ra8!?"]);
+    }
+}
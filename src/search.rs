@@ -0,0 +1,319 @@
+use crate::endgame;
+use crate::evaluate;
+use crate::nnue::{self, Accumulator, Network};
+use crate::position::{Move, Position};
+use crate::syzygy;
+use crate::Kind;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const MATE: i32 = 100_000;
+// Tablebase wins rank below every mate found by the search.
+pub const TB_WIN: i32 = MATE - 1000;
+const INF: i32 = 1_000_000;
+const MAX_PLY: usize = 64;
+
+const VALUES: [i32; 6] = [1, 3, 3, 5, 9, 0];
+
+#[derive(Copy, Clone, Debug)]
+pub struct Limits {
+    pub depth: u32,
+    pub nodes: u64,
+    // The search stops once this has passed, and no new iteration starts after half of it.
+    pub time: Option<Duration>,
+}
+
+impl Limits {
+    pub fn depth(depth: u32) -> Self {
+        Limits { depth, nodes: u64::MAX, time: None }
+    }
+
+    pub fn nodes(nodes: u64) -> Self {
+        Limits { depth: MAX_PLY as u32, nodes, time: None }
+    }
+
+    // Until stopped from outside, or a mate is found.
+    pub fn infinite() -> Self {
+        Limits::nodes(u64::MAX)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best: Option<Move>,
+    // Centi-units of `evaluate`, from the side to move's point of view.
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+}
+
+struct Searcher<'a> {
+    limits: Limits,
+    stop: &'a AtomicBool,
+    start: Instant,
+    nodes: u64,
+    stopped: bool,
+    history: Vec<u64>,
+    // The network is held for the whole search, so that loading another one does not change it midway.
+    acc: Option<(Arc<Network>, Accumulator)>,
+}
+
+// `history` holds the hashes of the positions played before `pos`, for repetition detection.
+pub fn search(pos: &Position, history: &[u64], limits: Limits) -> SearchResult {
+    search_with(pos, history, limits, &AtomicBool::new(false), |_| {})
+}
+
+// Like `search`, but also stops once `stop` is set, and hands every finished iteration to `report`.
+pub fn search_with(pos: &Position, history: &[u64], limits: Limits, stop: &AtomicBool, mut report: impl FnMut(&SearchResult)) -> SearchResult {
+    let mut s = Searcher {
+        limits,
+        stop,
+        start: Instant::now(),
+        nodes: 0,
+        stopped: false,
+        history: history.to_vec(),
+        acc: accumulator(pos),
+    };
+
+    let mut result = SearchResult {
+        best: pos.legal_moves().first().copied(),
+        score: 0,
+        depth: 0,
+        nodes: 0,
+        pv: Vec::new(),
+    };
+
+    // With no legal moves the game is over: there is nothing to search, but the result still
+    // goes to `report` as mate or stalemate, with an empty principal variation.
+    if result.best.is_none() {
+        result.score = if pos.in_check(pos.white) {-MATE} else {0};
+        report(&result);
+        return result;
+    }
+
+    if let Some((m, wdl)) = syzygy::probe_root(pos) {
+        result.best = Some(m);
+        result.score = tb_score(wdl, 0);
+        result.pv = vec![m];
+        report(&result);
+        return result;
+    }
+
+    for depth in 1..=limits.depth.max(1) {
+        let mut pv = Vec::new();
+        let score = s.negamax(pos, depth as i32, -INF, INF, 0, &result.pv, &mut pv);
+
+        if s.stopped && depth > 1 {break}
+
+        if !pv.is_empty() {
+            result.best = pv.first().copied();
+            result.score = score;
+            result.depth = depth;
+            result.pv = pv;
+            result.nodes = s.nodes;
+            report(&result);
+        }
+
+        if s.stopped || score.abs() >= MATE - MAX_PLY as i32 {break}
+        if limits.time.is_some_and(|t| s.start.elapsed() * 2 >= t) {break}
+    }
+
+    result.nodes = s.nodes;
+    result
+}
+
+// A position is quiet when the side to move is not in check and no capture sequence changes the static score.
+pub fn is_quiet(pos: &Position) -> bool {
+    if pos.in_check(pos.white) {return false}
+
+    let mut s = Searcher {
+        limits: Limits::depth(0),
+        stop: &AtomicBool::new(false),
+        start: Instant::now(),
+        nodes: 0,
+        stopped: false,
+        history: Vec::new(),
+        acc: accumulator(pos),
+    };
+
+    s.static_eval(pos) == s.qsearch(pos, -INF, INF, 0)
+}
+
+fn accumulator(pos: &Position) -> Option<(Arc<Network>, Accumulator)> {
+    if !nnue::enabled() {return None}
+
+    let net = nnue::network();
+    let acc = Accumulator::refresh(&net, &pos.board);
+    Some((net, acc))
+}
+
+impl Searcher<'_> {
+    fn static_eval(&self, pos: &Position) -> i32 {
+        let white = match &self.acc {
+            Some((net, acc)) => endgame::evaluate(&pos.board, pos.white, || acc.output(net)),
+            None => evaluate(pos.board, pos.white),
+        };
+
+        let score = (white * 100.).round() as i32;
+        if pos.white {score} else {-score}
+    }
+
+    fn make(&mut self, pos: &Position, m: Move) -> Position {
+        if let Some((net, acc)) = self.acc.as_mut() {acc.make(net, &pos.board, m)}
+        self.history.push(pos.hash());
+        pos.make(m)
+    }
+
+    fn unmake(&mut self, pos: &Position, m: Move) {
+        if let Some((net, acc)) = self.acc.as_mut() {acc.unmake(net, &pos.board, m)}
+        self.history.pop();
+    }
+
+    fn ordered(&self, pos: &Position, mut moves: Vec<Move>, first: Option<Move>) -> Vec<Move> {
+        moves.sort_by_cached_key(|&m| {
+            if Some(m) == first {return i32::MIN}
+
+            let mut score = 0;
+
+            if pos.is_capture(m) {
+                let victim = pos.board[m.3][m.2].map_or(VALUES[0], |p| VALUES[p.kind.index()]);
+                let attacker = pos.board[m.1][m.0].map_or(0, |p| VALUES[p.kind.index()]);
+                score += 1000 + victim * 10 - attacker;
+            }
+            // Underpromotions go after the quiet moves.
+            match m.4 {
+                Some(Kind::Queen) => score += 900,
+                Some(_) => score -= 900,
+                None => {},
+            }
+
+            -score
+        });
+
+        moves
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(&mut self, pos: &Position, depth: i32, mut alpha: i32, beta: i32, ply: usize, prev_pv: &[Move], pv: &mut Vec<Move>) -> i32 {
+        self.nodes += 1;
+        if self.nodes >= self.limits.nodes {self.stopped = true}
+        if self.nodes.is_multiple_of(1024) && (self.stop.load(Ordering::Relaxed) || self.limits.time.is_some_and(|t| self.start.elapsed() >= t)) {
+            self.stopped = true;
+        }
+        if self.stopped && ply > 0 {return 0}
+
+        if ply > 0 {
+            let hash = pos.hash();
+            if pos.halfmove >= 100 || self.history.contains(&hash) {return 0}
+
+            // After a capture or pawn move the WDL result is exact, so the subtree can be cut.
+            if pos.halfmove == 0 && syzygy::can_probe(pos) {
+                if let Some(wdl) = syzygy::probe_wdl(pos) {return tb_score(wdl, ply)}
+            }
+        }
+
+        let in_check = pos.in_check(pos.white);
+        let depth = if in_check {depth + 1} else {depth};
+
+        if depth <= 0 || ply >= MAX_PLY {
+            return self.qsearch(pos, alpha, beta, ply);
+        }
+
+        let moves = pos.legal_moves();
+        if moves.is_empty() {
+            return if in_check {-MATE + ply as i32} else {0};
+        }
+
+        let first = prev_pv.first().copied();
+        let mut best = -INF;
+
+        for m in self.ordered(pos, moves, first) {
+            let next = self.make(pos, m);
+
+            let mut child = Vec::new();
+            let rest = if Some(m) == first {&prev_pv[1..]} else {&[]};
+            let score = -self.negamax(&next, depth - 1, -beta, -alpha, ply + 1, rest, &mut child);
+
+            self.unmake(pos, m);
+
+            if self.stopped {
+                if ply > 0 {return 0}
+                break;
+            }
+
+            if score > best {
+                best = score;
+
+                if score > alpha {
+                    alpha = score;
+
+                    pv.clear();
+                    pv.push(m);
+                    pv.extend(child);
+                }
+            }
+
+            if alpha >= beta {break}
+        }
+
+        best
+    }
+
+    fn qsearch(&mut self, pos: &Position, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        self.nodes += 1;
+
+        if ply >= 2 * MAX_PLY {return self.static_eval(pos)}
+
+        let in_check = pos.in_check(pos.white);
+
+        let mut best = -INF;
+
+        if !in_check {
+            best = self.static_eval(pos);
+
+            if best >= beta || ply >= MAX_PLY {return best}
+            if best > alpha {alpha = best}
+        }
+
+        let mut moves = pos.legal_moves();
+
+        if moves.is_empty() {
+            return if in_check {-MATE + ply as i32} else {0};
+        }
+
+        if !in_check {
+            // Captures and queen promotions; underpromotions are left to the main search.
+            moves.retain(|&m| (pos.is_capture(m) || m.4.is_some()) && m.4.is_none_or(|k| k == Kind::Queen));
+        }
+
+        for m in self.ordered(pos, moves, None) {
+            let next = self.make(pos, m);
+            let score = -self.qsearch(&next, -beta, -alpha, ply + 1);
+            self.unmake(pos, m);
+
+            if score > best {
+                best = score;
+                if score > alpha {alpha = score}
+            }
+
+            if alpha >= beta {break}
+        }
+
+        best
+    }
+}
+
+// Cursed wins and blessed losses are draws under the 50-move rule.
+fn tb_score(wdl: i32, ply: usize) -> i32 {
+    match wdl {
+        syzygy::WIN => TB_WIN - ply as i32,
+        syzygy::LOSS => -TB_WIN + ply as i32,
+        _ => 0,
+    }
+}
+
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE - 2 * MAX_PLY as i32
+}
@@ -0,0 +1,918 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+use crate::endgame::material;
+use crate::position::{Move, Position};
+use crate::Kind;
+
+// Probing code for Syzygy WDL (.rtbw) and DTZ (.rtbz) tables, following the layout used by
+// the reference prober: squares are numbered a1 = 0 .. h8 = 63 and pieces are coded 1..6
+// (pawn..king) for white and 9..14 for black.
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+pub const LOSS: i32 = -2;
+pub const BLESSED_LOSS: i32 = -1;
+pub const DRAW: i32 = 0;
+pub const CURSED_WIN: i32 = 1;
+pub const WIN: i32 = 2;
+
+const MAX_PIECES: usize = 7;
+
+struct Tablebase {
+    dir: PathBuf,
+    // Material code (e.g. "KRvK") -> canonical file stem, for both colour orientations.
+    names: HashMap<String, String>,
+    max_pieces: usize,
+    wdl: Mutex<HashMap<String, Option<Arc<Table>>>>,
+    dtz: Mutex<HashMap<String, Option<Arc<Table>>>>,
+}
+
+static TABLEBASE: RwLock<Option<Arc<Tablebase>>> = RwLock::new(None);
+
+// Replaces the tables in use by those in `dir`.
+pub fn init(dir: &str) -> Result<usize, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir, e))?;
+
+    let mut names = HashMap::new();
+    let mut max_pieces = 0;
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(stem) = name.strip_suffix(".rtbw") else {continue};
+
+        let Some((white, black)) = stem.split_once('v') else {continue};
+        let valid = |s: &str| s.starts_with('K') && s.chars().all(|c| "KQRBNP".contains(c));
+        if !valid(white) || !valid(black) || stem.len() - 1 > MAX_PIECES {continue}
+
+        max_pieces = max_pieces.max(stem.len() - 1);
+        names.insert(stem.to_string(), stem.to_string());
+        names.insert(format!("{}v{}", black, white), stem.to_string());
+    }
+
+    let count = names.values().collect::<std::collections::HashSet<_>>().len();
+
+    *TABLEBASE.write().unwrap() = Some(Arc::new(Tablebase {
+        dir: PathBuf::from(dir),
+        names,
+        max_pieces,
+        wdl: Mutex::new(HashMap::new()),
+        dtz: Mutex::new(HashMap::new()),
+    }));
+
+    Ok(count)
+}
+
+pub fn unload() {
+    *TABLEBASE.write().unwrap() = None;
+}
+
+pub fn max_pieces() -> usize {
+    TABLEBASE.read().unwrap().as_ref().map_or(0, |tb| tb.max_pieces)
+}
+
+pub fn piece_count(pos: &Position) -> usize {
+    pos.board.iter().flatten().flatten().count()
+}
+
+// Tables assume no castling rights; positions with more pieces than the largest table are skipped.
+pub fn can_probe(pos: &Position) -> bool {
+    max_pieces() > 0 && !pos.castling.contains(&true) && piece_count(pos) <= max_pieces()
+}
+
+fn table(dtz: bool, pos: &Position) -> Option<(Arc<Table>, String)> {
+    let tb = TABLEBASE.read().unwrap().clone()?;
+    let code = format!("{}v{}", material(&pos.board, true), material(&pos.board, false));
+    let stem = tb.names.get(&code)?;
+
+    let cache = if dtz {&tb.dtz} else {&tb.wdl};
+    let mut cache = cache.lock().unwrap();
+
+    let table = cache.entry(stem.clone()).or_insert_with(|| {
+        let path = tb.dir.join(format!("{}.{}", stem, if dtz {"rtbz"} else {"rtbw"}));
+        let bytes = std::fs::read(path).ok()?;
+        Table::new(stem, dtz, bytes).map(Arc::new)
+    });
+
+    table.clone().map(|t| (t, code))
+}
+
+// ---------------------------------------------------------------------------------------------
+// Index tables
+
+struct Indices {
+    map_b1h1h7: [u64; 64],
+    map_a1d1d4: [u64; 64],
+    map_kk: [[u64; 64]; 10],
+    binomial: [[u64; 64]; 6],
+    map_pawns: [u64; 64],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+fn file_of(sq: usize) -> usize {sq & 7}
+fn rank_of(sq: usize) -> usize {sq >> 3}
+fn off_a1h8(sq: usize) -> i32 {rank_of(sq) as i32 - file_of(sq) as i32}
+
+fn indices() -> &'static Indices {
+    static INDICES: OnceLock<Indices> = OnceLock::new();
+
+    INDICES.get_or_init(|| {
+        let mut ix = Indices {
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 6],
+            map_pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        let mut code = 0;
+        for sq in 0..64 {
+            if off_a1h8(sq) < 0 {
+                ix.map_b1h1h7[sq] = code;
+                code += 1;
+            }
+        }
+
+        let mut diagonal = Vec::new();
+        code = 0;
+        for sq in 0..=27 {
+            if off_a1h8(sq) < 0 && file_of(sq) <= 3 {
+                ix.map_a1d1d4[sq] = code;
+                code += 1;
+            } else if off_a1h8(sq) == 0 && file_of(sq) <= 3 {
+                diagonal.push(sq);
+            }
+        }
+        for sq in diagonal {
+            ix.map_a1d1d4[sq] = code;
+            code += 1;
+        }
+
+        let mut both_on_diagonal = Vec::new();
+        code = 0;
+        for idx in 0..10 {
+            for s1 in 0..=27 {
+                if ix.map_a1d1d4[s1] != idx as u64 || (idx == 0 && s1 != 1) {continue}
+
+                for s2 in 0..64 {
+                    let adjacent = file_of(s1).abs_diff(file_of(s2)) <= 1 && rank_of(s1).abs_diff(rank_of(s2)) <= 1;
+
+                    if adjacent || (off_a1h8(s1) == 0 && off_a1h8(s2) > 0) {continue}
+                    else if off_a1h8(s1) == 0 && off_a1h8(s2) == 0 {both_on_diagonal.push((idx, s2))}
+                    else {
+                        ix.map_kk[idx][s2] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            ix.map_kk[idx][s2] = code;
+            code += 1;
+        }
+
+        ix.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..6.min(n + 1) {
+                ix.binomial[k][n] = if k > 0 {ix.binomial[k - 1][n - 1]} else {0}
+                    + if k < n {ix.binomial[k][n - 1]} else {0};
+            }
+        }
+
+        let mut available = 47;
+        for lead in 1..=5 {
+            for f in 0..4 {
+                let mut idx = 0;
+
+                for r in 1..=6 {
+                    let sq = r * 8 + f;
+
+                    if lead == 1 {
+                        ix.map_pawns[sq] = available;
+                        ix.map_pawns[sq ^ 7] = available - 1;
+                        available = available.saturating_sub(2);
+                    }
+
+                    ix.lead_pawn_idx[lead][sq] = idx;
+                    idx += ix.binomial[lead - 1][ix.map_pawns[sq] as usize];
+                }
+
+                ix.lead_pawns_size[lead][f] = idx;
+            }
+        }
+
+        ix
+    })
+}
+
+// ---------------------------------------------------------------------------------------------
+// Table layout
+
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    pieces: [u8; MAX_PIECES],
+    group_len: [usize; MAX_PIECES + 1],
+    group_idx: [u64; MAX_PIECES + 1],
+    block_size: u64,
+    span: u64,
+    num_blocks: u64,
+    block_length_size: u64,
+    max_sym_len: u8,
+    min_sym_len: u8,
+    num_indices: u64,
+    lowest_sym: usize,
+    base64: Vec<u64>,
+    symlen: Vec<u8>,
+    btree: usize,
+    sparse_index: usize,
+    block_length: usize,
+    data: usize,
+    map_idx: [usize; 4],
+}
+
+struct Table {
+    dtz: bool,
+    key: String,
+    key2: String,
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    pawn_count: [usize; 2],
+    items: Vec<Vec<PairsData>>,
+    map: usize,
+    bytes: Vec<u8>,
+}
+
+impl Table {
+    fn new(stem: &str, dtz: bool, bytes: Vec<u8>) -> Option<Self> {
+        let magic = if dtz {DTZ_MAGIC} else {WDL_MAGIC};
+        if bytes.len() < 5 || bytes[..4] != magic {return None}
+
+        let (white, black) = stem.split_once('v')?;
+        let count = |s: &str, c: char| s.chars().filter(|&x| x == c).count();
+
+        let has_unique_pieces = [white, black].iter().any(|side| "QRBNP".chars().any(|c| count(side, c) == 1));
+
+        let (wp, bp) = (count(white, 'P'), count(black, 'P'));
+        let lead_white = bp == 0 || (wp > 0 && bp >= wp);
+
+        let mut t = Table {
+            dtz,
+            key: stem.to_string(),
+            key2: format!("{}v{}", black, white),
+            piece_count: stem.len() - 1,
+            has_pawns: wp + bp > 0,
+            has_unique_pieces,
+            pawn_count: if lead_white {[wp, bp]} else {[bp, wp]},
+            items: Vec::new(),
+            map: 0,
+            bytes,
+        };
+
+        t.setup()?;
+        Some(t)
+    }
+
+    fn sides(&self) -> usize {
+        if !self.dtz && self.key != self.key2 {2} else {1}
+    }
+
+    fn get(&self, stm: usize, f: usize) -> &PairsData {
+        &self.items[stm % self.sides()][if self.has_pawns {f} else {0}]
+    }
+
+    fn u8_at(&self, at: usize) -> Option<u8> {
+        self.bytes.get(at).copied()
+    }
+
+    fn u16_at(&self, at: usize) -> Option<u16> {
+        Some(u16::from_le_bytes(self.bytes.get(at..at + 2)?.try_into().ok()?))
+    }
+
+    fn u32_at(&self, at: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes.get(at..at + 4)?.try_into().ok()?))
+    }
+
+    fn setup(&mut self) -> Option<()> {
+        let mut data = 4;
+        let flags = self.u8_at(data)?;
+        data += 1;
+
+        if (flags & 2 != 0) != self.has_pawns || (flags & 1 != 0) != (self.key != self.key2) {return None}
+
+        let sides = self.sides();
+        let max_file = if self.has_pawns {3} else {0};
+        let pp = self.has_pawns && self.pawn_count[1] > 0;
+
+        self.items = vec![vec![PairsData::default(); max_file + 1]; sides];
+
+        for f in 0..=max_file {
+            let b0 = self.u8_at(data)?;
+            let b1 = if pp {self.u8_at(data + 1)?} else {0};
+            let order = [[b0 & 0xF, if pp {b1 & 0xF} else {0xF}], [b0 >> 4, if pp {b1 >> 4} else {0xF}]];
+            data += 1 + pp as usize;
+
+            for k in 0..self.piece_count {
+                let b = self.u8_at(data)?;
+                for i in 0..sides {
+                    self.items[i][f].pieces[k] = if i == 1 {b >> 4} else {b & 0xF};
+                }
+                data += 1;
+            }
+
+            for (i, order) in order.iter().enumerate().take(sides) {
+                self.set_groups(i, f, *order);
+            }
+        }
+
+        data += data & 1;
+
+        for f in 0..=max_file {
+            for i in 0..sides {
+                data = self.set_sizes(i, f, data)?;
+            }
+        }
+
+        if self.dtz {
+            self.map = data;
+
+            for f in 0..=max_file {
+                let flags = self.items[0][f].flags;
+                if flags & FLAG_MAPPED == 0 {continue}
+
+                if flags & FLAG_WIDE != 0 {
+                    data += data & 1;
+                    for i in 0..4 {
+                        self.items[0][f].map_idx[i] = (data - self.map) / 2 + 1;
+                        data += 2 * self.u16_at(data)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        self.items[0][f].map_idx[i] = data - self.map + 1;
+                        data += self.u8_at(data)? as usize + 1;
+                    }
+                }
+            }
+
+            data += data & 1;
+        }
+
+        for f in 0..=max_file {
+            for i in 0..sides {
+                self.items[i][f].sparse_index = data;
+                data += self.items[i][f].num_indices as usize * 6;
+            }
+        }
+
+        for f in 0..=max_file {
+            for i in 0..sides {
+                self.items[i][f].block_length = data;
+                data += self.items[i][f].block_length_size as usize * 2;
+            }
+        }
+
+        for f in 0..=max_file {
+            for i in 0..sides {
+                data = (data + 0x3F) & !0x3F;
+                self.items[i][f].data = data;
+                data += (self.items[i][f].num_blocks * self.items[i][f].block_size) as usize;
+            }
+        }
+
+        if data > self.bytes.len() {return None}
+
+        Some(())
+    }
+
+    // Pieces of the same kind and colour are encoded together; without pawns the leading
+    // group is either three unique pieces or the two kings.
+    fn set_groups(&mut self, side: usize, f: usize, order: [u8; 2]) {
+        let ix = indices();
+        let (has_pawns, unique, pawn_count, piece_count) = (self.has_pawns, self.has_unique_pieces, self.pawn_count, self.piece_count);
+        let d = &mut self.items[side][f];
+
+        let mut n = 0;
+        let mut first_len: i32 = if has_pawns {0} else if unique {3} else {2};
+        d.group_len[n] = 1;
+
+        for i in 1..piece_count {
+            first_len -= 1;
+            if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+                d.group_len[n] += 1;
+            } else {
+                n += 1;
+                d.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        d.group_len[n] = 0;
+
+        let pp = has_pawns && pawn_count[1] > 0;
+        let mut next = if pp {2} else {1};
+        let mut free_squares = 64 - d.group_len[0] - if pp {d.group_len[1]} else {0};
+        let mut idx: u64 = 1;
+
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                d.group_idx[0] = idx;
+                idx *= if has_pawns {ix.lead_pawns_size[d.group_len[0]][f]} else if unique {31332} else {462};
+            } else if k == order[1] as usize {
+                d.group_idx[1] = idx;
+                idx *= ix.binomial[d.group_len[1]][48 - d.group_len[0]];
+            } else {
+                d.group_idx[next] = idx;
+                idx *= ix.binomial[d.group_len[next]][free_squares];
+                free_squares -= d.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+
+        d.group_idx[n] = idx;
+    }
+
+    fn set_sizes(&mut self, side: usize, f: usize, mut data: usize) -> Option<usize> {
+        let flags = self.u8_at(data)?;
+        data += 1;
+
+        if flags & FLAG_SINGLE_VALUE != 0 {
+            let value = self.u8_at(data)?;
+            let d = &mut self.items[side][f];
+            d.flags = flags;
+            d.min_sym_len = value;
+            return Some(data + 1);
+        }
+
+        let block_size = 1u64 << self.u8_at(data)?;
+        let span = 1u64 << self.u8_at(data + 1)?;
+        let padding = self.u8_at(data + 2)? as u64;
+        let num_blocks = self.u32_at(data + 3)? as u64;
+        let max_sym_len = self.u8_at(data + 7)?;
+        let min_sym_len = self.u8_at(data + 8)?;
+        let lowest_sym = data + 9;
+
+        if min_sym_len == 0 || max_sym_len < min_sym_len {return None}
+
+        let len = (max_sym_len - min_sym_len + 1) as usize;
+        let mut base64 = vec![0u64; len];
+
+        for i in (0..len.saturating_sub(1)).rev() {
+            let lo = self.u16_at(lowest_sym + 2 * i)? as u64;
+            let lo_next = self.u16_at(lowest_sym + 2 * (i + 1))? as u64;
+            base64[i] = (base64[i + 1] + lo - lo_next) / 2;
+        }
+        for (i, b) in base64.iter_mut().enumerate() {
+            *b = b.checked_shl(64 - i as u32 - min_sym_len as u32).unwrap_or(0);
+        }
+
+        data = lowest_sym + len * 2;
+        let symbols = self.u16_at(data)? as usize;
+        data += 2;
+        let btree = data;
+
+        let mut symlen = vec![0u8; symbols];
+        let mut visited = vec![false; symbols];
+        for s in 0..symbols {
+            if !visited[s] {
+                symlen[s] = self.set_symlen(btree, s, &mut symlen, &mut visited)?;
+            }
+        }
+
+        let group_end = self.items[side][f].group_len.iter().position(|&g| g == 0)?;
+        let size = self.items[side][f].group_idx[group_end];
+
+        let d = &mut self.items[side][f];
+        d.flags = flags;
+        d.block_size = block_size;
+        d.span = span;
+        d.num_indices = size.div_ceil(span);
+        d.num_blocks = num_blocks;
+        d.block_length_size = num_blocks + padding;
+        d.max_sym_len = max_sym_len;
+        d.min_sym_len = min_sym_len;
+        d.lowest_sym = lowest_sym;
+        d.base64 = base64;
+        d.symlen = symlen;
+        d.btree = btree;
+
+        Some(btree + symbols * 3 + (symbols & 1))
+    }
+
+    fn pair(&self, btree: usize, s: usize) -> Option<(usize, usize)> {
+        let at = btree + 3 * s;
+        let (b0, b1, b2) = (self.u8_at(at)? as usize, self.u8_at(at + 1)? as usize, self.u8_at(at + 2)? as usize);
+        Some((((b1 & 0xF) << 8) | b0, (b2 << 4) | (b1 >> 4)))
+    }
+
+    fn set_symlen(&self, btree: usize, s: usize, symlen: &mut [u8], visited: &mut [bool]) -> Option<u8> {
+        visited[s] = true;
+        let (left, right) = self.pair(btree, s)?;
+
+        if right == 0xFFF {return Some(0)}
+        if left >= symlen.len() || right >= symlen.len() {return None}
+
+        if !visited[left] {symlen[left] = self.set_symlen(btree, left, symlen, visited)?}
+        if !visited[right] {symlen[right] = self.set_symlen(btree, right, symlen, visited)?}
+
+        Some(symlen[left].wrapping_add(symlen[right]).wrapping_add(1))
+    }
+
+    fn decompress(&self, d: &PairsData, idx: u64) -> Option<usize> {
+        if d.flags & FLAG_SINGLE_VALUE != 0 {return Some(d.min_sym_len as usize)}
+
+        let k = (idx / d.span) as usize;
+        let mut block = self.u32_at(d.sparse_index + 6 * k)? as i64;
+        let mut offset = self.u16_at(d.sparse_index + 6 * k + 4)? as i64;
+
+        offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+
+        let block_length = |b: i64| self.u16_at(d.block_length + 2 * b as usize).map(|l| l as i64);
+
+        while offset < 0 {
+            block -= 1;
+            offset += block_length(block)? + 1;
+        }
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        let mut ptr = d.data + block as usize * d.block_size as usize;
+        // The last block of a file can be read past its end, where there is nothing but zeros.
+        let read32 = |at: usize| (at..at + 4).fold(0, |w, i| w << 8 | self.u8_at(i).unwrap_or(0) as u64);
+
+        let mut buf = (read32(ptr) << 32) | read32(ptr + 4);
+        ptr += 8;
+        let mut buf_size = 64;
+
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while buf < d.base64[len] {
+                len += 1;
+                if len >= d.base64.len() {return None}
+            }
+
+            sym = ((buf - d.base64[len]) >> (64 - len - d.min_sym_len as usize)) as usize;
+            sym += self.u16_at(d.lowest_sym + 2 * len)? as usize;
+
+            if sym >= d.symlen.len() {return None}
+            if offset < d.symlen[sym] as i64 + 1 {break}
+
+            offset -= d.symlen[sym] as i64 + 1;
+            len += d.min_sym_len as usize;
+            buf <<= len;
+            buf_size -= len;
+
+            if buf_size <= 32 {
+                buf_size += 32;
+                buf |= read32(ptr) << (64 - buf_size);
+                ptr += 4;
+            }
+        }
+
+        while d.symlen[sym] != 0 {
+            let (left, right) = self.pair(d.btree, sym)?;
+
+            if offset < d.symlen[left] as i64 + 1 {
+                sym = left;
+            } else {
+                offset -= d.symlen[left] as i64 + 1;
+                sym = right;
+            }
+        }
+
+        self.pair(d.btree, sym).map(|(left, _)| left)
+    }
+
+    // Maps a position to its table index and reads the stored value. `None` means the
+    // DTZ table holds the other side to move.
+    fn probe(&self, pos: &Position, code: &str, wdl: i32) -> Option<Option<i32>> {
+        let ix = indices();
+
+        let black_symmetric = !pos.white && self.key == self.key2;
+        let black_stronger = code != self.key;
+        let flip = black_symmetric || black_stronger;
+
+        let flip_color = if flip {8} else {0};
+        let flip_squares = if flip {56} else {0};
+        let stm = (flip as usize) ^ (!pos.white as usize);
+
+        let mut squares = [0usize; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_count = 0;
+        let mut tb_file = 0;
+
+        let occupied: Vec<(usize, u8)> = (0..64).filter_map(|sq| {
+            let (x, y) = (sq & 7, 7 - (sq >> 3));
+            pos.board[y][x].map(|p| (sq, (p.kind.index() + 1) as u8 + if p.white {0} else {8}))
+        }).collect();
+
+        let mut lead_code = 0;
+
+        if self.has_pawns {
+            lead_code = self.get(0, 0).pieces[0] ^ flip_color;
+
+            for &(sq, code) in &occupied {
+                if code == lead_code {
+                    squares[size] = sq ^ flip_squares;
+                    size += 1;
+                }
+            }
+            lead_count = size;
+
+            let best = (0..lead_count).max_by_key(|&i| (ix.map_pawns[squares[i]], std::cmp::Reverse(i)))?;
+            squares.swap(0, best);
+
+            tb_file = file_of(squares[0]).min(7 - file_of(squares[0]));
+        }
+
+        if self.dtz {
+            let flags = self.get(stm, tb_file).flags;
+            if (flags & FLAG_STM) as usize != stm && (self.has_pawns || self.key != self.key2) {
+                return Some(None);
+            }
+        }
+
+        for &(sq, code) in &occupied {
+            if self.has_pawns && code == lead_code {continue}
+            squares[size] = sq ^ flip_squares;
+            pieces[size] = code ^ flip_color;
+            size += 1;
+        }
+
+        if size < 2 || size != self.piece_count {return None}
+
+        let d = self.get(stm, tb_file);
+
+        for i in lead_count..size - 1 {
+            for j in i + 1..size {
+                if d.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        if file_of(squares[0]) > 3 {
+            for sq in squares.iter_mut().take(size) {*sq ^= 7}
+        }
+
+        let mut idx: u64;
+
+        if self.has_pawns {
+            idx = ix.lead_pawn_idx[lead_count][squares[0]];
+
+            squares[1..lead_count].sort_by_key(|&sq| ix.map_pawns[sq]);
+
+            for (i, &sq) in squares.iter().enumerate().take(lead_count).skip(1) {
+                idx += ix.binomial[i][ix.map_pawns[sq] as usize];
+            }
+        } else {
+            if rank_of(squares[0]) > 3 {
+                for sq in squares.iter_mut().take(size) {*sq ^= 56}
+            }
+
+            for i in 0..d.group_len[0] {
+                if off_a1h8(squares[i]) == 0 {continue}
+
+                if off_a1h8(squares[i]) > 0 {
+                    for sq in squares.iter_mut().take(size).skip(i) {
+                        *sq = ((*sq >> 3) | (*sq << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            if self.has_unique_pieces {
+                let adjust1 = (squares[1] > squares[0]) as u64;
+                let adjust2 = (squares[2] > squares[0]) as u64 + (squares[2] > squares[1]) as u64;
+                let (s1, s2) = (squares[1] as u64, squares[2] as u64);
+                let r = |sq: usize| rank_of(sq) as u64;
+
+                idx = if off_a1h8(squares[0]) != 0 {
+                    (ix.map_a1d1d4[squares[0]] * 63 + (s1 - adjust1)) * 62 + s2 - adjust2
+                } else if off_a1h8(squares[1]) != 0 {
+                    (6 * 63 + r(squares[0]) * 28 + ix.map_b1h1h7[squares[1]]) * 62 + s2 - adjust2
+                } else if off_a1h8(squares[2]) != 0 {
+                    6 * 63 * 62 + 4 * 28 * 62 + r(squares[0]) * 7 * 28 + (r(squares[1]) - adjust1) * 28 + ix.map_b1h1h7[squares[2]]
+                } else {
+                    6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + r(squares[0]) * 7 * 6 + (r(squares[1]) - adjust1) * 6 + (r(squares[2]) - adjust2)
+                };
+            } else {
+                idx = ix.map_kk[ix.map_a1d1d4[squares[0]] as usize][squares[1]];
+            }
+        }
+
+        idx *= d.group_idx[0];
+
+        let mut start = d.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            squares[start..start + len].sort_unstable();
+
+            let mut n = 0;
+            for i in 0..len {
+                let sq = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| sq > s).count();
+                let pos = sq - adjust - if remaining_pawns {8} else {0};
+                n += ix.binomial[i + 1][pos];
+            }
+
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            start += len;
+            next += 1;
+        }
+
+        let value = self.decompress(d, idx)? as i32;
+
+        if !self.dtz {return Some(Some(value - 2))}
+
+        Some(Some(self.map_dtz(tb_file, value, wdl)?))
+    }
+
+    fn map_dtz(&self, f: usize, value: i32, wdl: i32) -> Option<i32> {
+        const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+
+        let d = self.get(0, f);
+        let mut value = value;
+
+        if d.flags & FLAG_MAPPED != 0 {
+            let i = d.map_idx[WDL_MAP[(wdl + 2) as usize]];
+
+            value = if d.flags & FLAG_WIDE != 0 {
+                self.u16_at(self.map + 2 * (i + value as usize))? as i32
+            } else {
+                self.u8_at(self.map + i + value as usize)? as i32
+            };
+        }
+
+        if (wdl == WIN && d.flags & FLAG_WIN_PLIES == 0)
+            || (wdl == LOSS && d.flags & FLAG_LOSS_PLIES == 0)
+            || wdl == CURSED_WIN
+            || wdl == BLESSED_LOSS {
+            value *= 2;
+        }
+
+        Some(value + 1)
+    }
+}
+
+// ---------------------------------------------------------------------------------------------
+// Probing
+
+enum State {
+    Ok,
+    ZeroingBest,
+}
+
+fn probe_table(dtz: bool, pos: &Position, wdl: i32) -> Option<Option<i32>> {
+    if piece_count(pos) == 2 {return Some(Some(DRAW))}
+
+    let (table, code) = table(dtz, pos)?;
+    table.probe(pos, &code, wdl)
+}
+
+// Captures (and, for DTZ, pawn moves) are not reliably stored, so they are searched first.
+fn search(pos: &Position, zeroing: bool) -> Option<(i32, State)> {
+    let moves = pos.legal_moves();
+    let mut best = LOSS;
+    let mut count = 0;
+
+    for &m in &moves {
+        let pawn = matches!(pos.board[m.1][m.0], Some(p) if p.kind == Kind::Pawn);
+        if !(pos.is_capture(m) || zeroing && pawn) {continue}
+
+        count += 1;
+
+        let (value, _) = search(&pos.make(m), false)?;
+        let value = -value;
+
+        if value > best {
+            best = value;
+            if value >= WIN {return Some((value, State::ZeroingBest))}
+        }
+    }
+
+    let no_more_moves = count > 0 && count == moves.len();
+
+    let value = if no_more_moves {
+        best
+    } else {
+        probe_table(false, pos, DRAW)??
+    };
+
+    if best >= value {
+        let state = if best > DRAW || no_more_moves {State::ZeroingBest} else {State::Ok};
+        return Some((best, state));
+    }
+
+    Some((value, State::Ok))
+}
+
+pub fn probe_wdl(pos: &Position) -> Option<i32> {
+    search(pos, false).map(|(v, _)| v)
+}
+
+fn dtz_before_zeroing(wdl: i32) -> i32 {
+    match wdl {
+        WIN => 1,
+        CURSED_WIN => 101,
+        BLESSED_LOSS => -101,
+        LOSS => -1,
+        _ => 0,
+    }
+}
+
+pub fn probe_dtz(pos: &Position) -> Option<i32> {
+    let (wdl, state) = search(pos, true)?;
+
+    if wdl == DRAW {return Some(0)}
+    if let State::ZeroingBest = state {return Some(dtz_before_zeroing(wdl))}
+
+    if let Some(dtz) = probe_table(true, pos, wdl)? {
+        let cursed = wdl == BLESSED_LOSS || wdl == CURSED_WIN;
+        return Some((dtz + if cursed {100} else {0}) * wdl.signum());
+    }
+
+    // The table stores the other side to move: do a 1-ply search for the best DTZ.
+    let mut min_dtz = 0xFFFF;
+
+    for m in pos.legal_moves() {
+        let zeroing = pos.is_capture(m) || matches!(pos.board[m.1][m.0], Some(p) if p.kind == Kind::Pawn);
+        let next = pos.make(m);
+
+        let mut dtz = if zeroing {
+            -dtz_before_zeroing(probe_wdl(&next)?)
+        } else {
+            -probe_dtz(&next)?
+        };
+
+        if dtz == 1 && next.in_check(next.white) && next.legal_moves().is_empty() {min_dtz = 1}
+
+        if !zeroing {dtz += dtz.signum()}
+
+        if dtz < min_dtz && dtz.signum() == wdl.signum() {min_dtz = dtz}
+    }
+
+    Some(if min_dtz == 0xFFFF {-1} else {min_dtz})
+}
+
+// Ranks the root moves by DTZ, preferring the fastest win that beats the 50-move rule and the
+// longest resistance when lost. Returns the chosen move and its WDL from the mover's side.
+pub fn probe_root(pos: &Position) -> Option<(Move, i32)> {
+    if !can_probe(pos) {return None}
+
+    let cnt50 = pos.halfmove as i32;
+    let mut best: Option<(i32, Move, i32)> = None;
+
+    for m in pos.legal_moves() {
+        let next = pos.make(m);
+
+        let mut dtz = if next.halfmove == 0 {
+            dtz_before_zeroing(-probe_wdl(&next)?)
+        } else if next.halfmove >= 100 {
+            0
+        } else {
+            let d = -probe_dtz(&next)?;
+            d + d.signum()
+        };
+
+        if next.in_check(next.white) && dtz == 2 && next.legal_moves().is_empty() {dtz = 1}
+
+        let (rank, wdl) = if dtz > 0 {
+            if dtz + cnt50 <= 100 {(3000 - dtz, WIN)} else {(1000 - dtz, CURSED_WIN)}
+        } else if dtz < 0 {
+            if -dtz + cnt50 > 100 {(-1000 + -dtz, BLESSED_LOSS)} else {(-3000 + -dtz, LOSS)}
+        } else {
+            (0, DRAW)
+        };
+
+        if best.is_none_or(|(r, _, _)| rank > r) {best = Some((rank, m, wdl))}
+    }
+
+    best.map(|(_, m, wdl)| (m, wdl))
+}
+
+#[cfg(test)]
+mod tests;
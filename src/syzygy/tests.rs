@@ -0,0 +1,885 @@
+use std::collections::BinaryHeap;
+use std::cmp::Reverse;
+use std::sync::Once;
+
+use super::*;
+use crate::{fen, Piece};
+
+// The tables under test-data/syzygy are small enough to be solved here from scratch. `generate`
+// writes them with an encoder of its own that shares nothing with the prober, so the tests below
+// check the prober's indexing, decompression and DTZ conventions against it, against known
+// positions and against the one-ply consistency every table has to satisfy.
+
+const DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test-data/syzygy");
+
+// In the order they are solved, as captures and promotions lead into the tables before them.
+// With them, per file, the side to move DTZ is kept for and whether it is mapped, so that the
+// prober meets both sides and both ways of storing DTZ.
+const TABLES: [(&str, [usize; 4], bool); 5] = [
+    ("KQvK", [0; 4], false),
+    ("KRvK", [1; 4], true),
+    ("KBvK", [0; 4], false),
+    ("KNvK", [0; 4], false),
+    ("KPvK", [0, 1, 1, 0], true),
+];
+
+fn setup() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| assert_eq!(init(DIR), Ok(TABLES.len())));
+}
+
+fn position(fen: &str) -> Position {
+    fen::parse(fen).unwrap()
+}
+
+#[test]
+fn wdl_of_known_positions() {
+    setup();
+
+    let cases = [
+        ("8/8/8/4k3/8/8/8/KQ6 w - - 0 1", WIN),
+        ("8/8/8/4k3/8/8/8/KQ6 b - - 0 1", LOSS),
+        // The queen is lost at once.
+        ("8/8/8/4k3/3Q4/8/8/7K b - - 0 1", DRAW),
+        ("8/8/8/4k3/8/8/8/KR6 b - - 0 1", LOSS),
+        ("8/8/8/4k3/8/8/8/KB6 w - - 0 1", DRAW),
+        ("8/8/8/4k3/8/8/8/KN6 w - - 0 1", DRAW),
+        // The king on the sixth rank in front of its pawn wins whoever is to move.
+        ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", WIN),
+        ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", LOSS),
+        // Rook pawns only draw with the defending king in the corner.
+        ("k7/8/8/PK6/8/8/8/8 w - - 0 1", DRAW),
+        // Black to move is stored for KRvK, white to move is found through a move.
+        ("8/8/8/8/2k5/8/8/K6R b - - 0 1", LOSS),
+        ("8/8/8/8/2k5/8/8/K6R w - - 0 1", WIN),
+        // Stored the other way round: black's king and rook.
+        ("8/8/8/8/2K5/8/8/k6r w - - 0 1", LOSS),
+    ];
+
+    for (fen, wdl) in cases {
+        assert_eq!(probe_wdl(&position(fen)), Some(wdl), "{}", fen);
+    }
+}
+
+#[test]
+fn dtz_of_known_positions() {
+    setup();
+
+    let cases = [
+        // Ra8 mates.
+        ("7k/8/6K1/8/8/8/8/R7 w - - 0 1", 1),
+        ("R6k/8/6K1/8/8/8/8/8 b - - 1 1", -1),
+        // Kg8 is forced, then Rb8 mates.
+        ("7k/1R6/6K1/8/8/8/8/8 b - - 0 1", -2),
+        ("6k1/1R6/6K1/8/8/8/8/8 w - - 1 1", 1),
+        // e4 wins at once, and a pawn move resets the count.
+        ("8/8/8/8/8/k7/4P3/4K3 w - - 0 1", 1),
+        ("8/8/8/4k3/8/8/8/KB6 w - - 0 1", 0),
+    ];
+
+    for (fen, dtz) in cases {
+        assert_eq!(probe_dtz(&position(fen)), Some(dtz), "{}", fen);
+    }
+}
+
+#[test]
+fn root_moves() {
+    setup();
+
+    assert_eq!(probe_root(&position("7k/8/6K1/8/8/8/8/R7 w - - 0 1")), Some(((0, 7, 0, 0, None), WIN)));
+    assert_eq!(probe_root(&position("6k1/1R6/6K1/8/8/8/8/8 w - - 1 1")), Some(((1, 1, 1, 0, None), WIN)));
+    assert_eq!(probe_root(&position("8/8/8/4k3/8/8/8/KB6 w - - 0 1")).map(|(_, wdl)| wdl), Some(DRAW));
+}
+
+// Every value has to follow from the values one move later: the best result over the moves,
+// and for DTZ the fastest win or the longest loss, where a move that mates or resets the count
+// on the way to the win counts as one ply. Positions are drawn at random from every table.
+#[test]
+fn values_agree_with_one_ply_lookahead() {
+    setup();
+
+    let mut rng = 0x9E37_79B9_7F4A_7C15u64;
+    let mut random = move || {
+        rng ^= rng << 13;
+        rng ^= rng >> 7;
+        rng ^= rng << 17;
+        rng
+    };
+
+    for (stem, _, _) in TABLES {
+        let mut checked = 0;
+
+        while checked < 100 {
+            let mut board = [[None; 8]; 8];
+            for (kind, white) in pieces(stem) {
+                let (x, y) = loop {
+                    let sq = random() as usize % 64;
+                    if board[sq / 8][sq % 8].is_none() {break (sq % 8, sq / 8)}
+                };
+                board[y][x] = Some(Piece { kind, white });
+            }
+
+            let pos = Position { board, white: random() % 2 == 0, castling: [false; 4], ep: None, halfmove: 0, fullmove: 1 };
+            if pos.validate().is_err() {continue}
+            checked += 1;
+
+            let moves = pos.legal_moves();
+            let wdl = probe_wdl(&pos).unwrap();
+            let dtz = probe_dtz(&pos).unwrap();
+
+            if moves.is_empty() {
+                let mated = pos.in_check(pos.white);
+                assert_eq!((wdl, dtz), if mated {(LOSS, -1)} else {(DRAW, 0)}, "{}", fen::to_fen(&pos));
+                continue;
+            }
+
+            let mut best = LOSS;
+            let mut plies = Vec::new();
+
+            for m in moves {
+                let next = pos.make(m);
+                let value = -probe_wdl(&next).unwrap();
+                let zeroing = pos.is_capture(m) || matches!(pos.board[m.1][m.0], Some(p) if p.kind == Kind::Pawn);
+                let mates = next.in_check(next.white) && next.legal_moves().is_empty();
+                let ply = if zeroing || mates {1} else {probe_dtz(&next).unwrap().abs() + 1};
+
+                if value > best {
+                    best = value;
+                    plies.clear();
+                }
+                if value == best {plies.push(ply)}
+            }
+
+            let expected = match best {
+                WIN => *plies.iter().min().unwrap(),
+                LOSS => -*plies.iter().max().unwrap(),
+                _ => 0,
+            };
+            assert_eq!((wdl, dtz), (best, expected), "{}", fen::to_fen(&pos));
+        }
+    }
+}
+
+fn pieces(stem: &str) -> Vec<(Kind, bool)> {
+    let (white, black) = stem.split_once('v').unwrap();
+    let kind = |c| match c {
+        'K' => Kind::King,
+        'Q' => Kind::Queen,
+        'R' => Kind::Rook,
+        'B' => Kind::Bishop,
+        'N' => Kind::Knight,
+        _ => Kind::Pawn,
+    };
+
+    white.chars().map(|c| (kind(c), true)).chain(black.chars().map(|c| (kind(c), false))).collect()
+}
+
+// ---------------------------------------------------------------------------------------------
+// Solving
+
+// A piece of a given colour on a square, numbered a1 = 0 .. h8 = 63 as in the tables.
+type Placed = (Kind, bool, u8);
+
+const UNKNOWN: i8 = 1;
+const ILLEGAL: i8 = i8::MAX;
+
+struct Solved {
+    // White's pieces and then black's, in the order of the stem.
+    pieces: Vec<(Kind, bool)>,
+    // From the side to move's point of view, for every placement by `index`.
+    wdl: Vec<i8>,
+    dtz: Vec<i16>,
+}
+
+impl Solved {
+    fn index(placed: &[Placed], white: bool) -> usize {
+        placed.iter().enumerate().fold((!white as usize) << (6 * placed.len()), |idx, (i, &(_, _, sq))| idx | (sq as usize) << (6 * i))
+    }
+
+    fn placed(&self, idx: usize) -> ([Placed; 4], bool) {
+        let mut placed = [(Kind::King, true, 0); 4];
+        for (i, &(kind, white)) in self.pieces.iter().enumerate() {
+            placed[i] = (kind, white, (idx >> (6 * i) & 63) as u8);
+        }
+
+        (placed, idx >> (6 * self.pieces.len()) == 0)
+    }
+}
+
+fn name(placed: &[Placed]) -> String {
+    let letter = |kind: Kind| "PNBRQK".as_bytes()[kind.index()] as char;
+    let side = |white: bool| placed.iter().filter(|p| p.1 == white).map(|p| letter(p.0)).collect::<String>();
+
+    format!("{}v{}", side(true), side(false))
+}
+
+// The WDL of a position in a table already solved, with the colours swapped if it is stored
+// the other way round.
+fn wdl_of(solved: &HashMap<String, Solved>, placed: &[Placed], white: bool) -> i8 {
+    if placed.len() == 2 {return DRAW as i8}
+
+    let order = |&(kind, white, _): &Placed| (!white, "KQRBNP".find("PNBRQK".as_bytes()[kind.index()] as char));
+    let mut placed = placed.to_vec();
+    placed.sort_by_key(order);
+
+    if let Some(t) = solved.get(&name(&placed)) {
+        return t.wdl[Solved::index(&placed, white)];
+    }
+
+    let mut flipped: Vec<Placed> = placed.iter().map(|&(kind, w, sq)| (kind, !w, sq ^ 56)).collect();
+    flipped.sort_by_key(order);
+
+    let t = &solved[&name(&flipped)];
+    t.wdl[Solved::index(&flipped, !white)]
+}
+
+fn step(sq: u8, (df, dr): (i32, i32)) -> Option<u8> {
+    let (f, r) = ((sq & 7) as i32 + df, (sq >> 3) as i32 + dr);
+    ((0..8).contains(&f) && (0..8).contains(&r)).then_some((r * 8 + f) as u8)
+}
+
+const KING: [(i32, i32); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+const KNIGHT: [(i32, i32); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+
+fn attacks(kind: Kind, white: bool, from: u8, to: u8, occupied: u64) -> bool {
+    let (df, dr) = ((to & 7) as i32 - (from & 7) as i32, (to >> 3) as i32 - (from >> 3) as i32);
+    let clear = || {
+        let dir = (df.signum(), dr.signum());
+        let mut sq = step(from, dir);
+        while let Some(s) = sq.filter(|&s| s != to) {
+            if occupied >> s & 1 != 0 {return false}
+            sq = step(s, dir);
+        }
+        true
+    };
+    let straight = (df == 0) != (dr == 0);
+    let diagonal = df != 0 && df.abs() == dr.abs();
+
+    match kind {
+        Kind::King => df.abs().max(dr.abs()) == 1,
+        Kind::Knight => df.abs() * dr.abs() == 2,
+        Kind::Pawn => df.abs() == 1 && dr == if white {1} else {-1},
+        Kind::Rook => straight && clear(),
+        Kind::Bishop => diagonal && clear(),
+        Kind::Queen => (straight || diagonal) && clear(),
+    }
+}
+
+fn in_check(placed: &[Placed], white: bool) -> bool {
+    let occupied = placed.iter().fold(0u64, |o, p| o | 1 << p.2);
+    let Some(&(_, _, king)) = placed.iter().find(|p| p.0 == Kind::King && p.1 == white) else {return false};
+
+    placed.iter().any(|&(kind, w, sq)| w != white && attacks(kind, w, sq, king, occupied))
+}
+
+// Moves of the side to move as (piece, target, promotion), including those that leave its king
+// in check. There are never castling rights, and with at most one pawn no en passant.
+fn moves(placed: &[Placed], white: bool, out: &mut Vec<(usize, u8, Option<Kind>)>) {
+    out.clear();
+    let at = |sq: u8| placed.iter().find(|p| p.2 == sq).map(|p| p.1);
+
+    for (i, &(kind, w, from)) in placed.iter().enumerate() {
+        if w != white {continue}
+
+        match kind {
+            Kind::Pawn => {
+                let dir = if white {1} else {-1};
+                let mut add = |to: u8| if to >> 3 == if white {7} else {0} {
+                    out.extend([Kind::Queen, Kind::Rook, Kind::Bishop, Kind::Knight].map(|k| (i, to, Some(k))));
+                } else {
+                    out.push((i, to, None));
+                };
+
+                if let Some(to) = step(from, (0, dir)).filter(|&to| at(to).is_none()) {
+                    add(to);
+                    let start = from >> 3 == if white {1} else {6};
+                    if let Some(to) = step(to, (0, dir)).filter(|&to| start && at(to).is_none()) {add(to)}
+                }
+                for df in [-1, 1] {
+                    if let Some(to) = step(from, (df, dir)).filter(|&to| at(to) == Some(!white)) {add(to)}
+                }
+            },
+            Kind::King | Kind::Knight => {
+                for d in if kind == Kind::King {KING} else {KNIGHT} {
+                    if let Some(to) = step(from, d).filter(|&to| at(to) != Some(white)) {out.push((i, to, None))}
+                }
+            },
+            _ => {
+                let dirs = match kind {Kind::Rook => &KING[..4], Kind::Bishop => &KING[4..], _ => &KING[..]};
+
+                for &d in dirs {
+                    let mut sq = from;
+                    while let Some(to) = step(sq, d) {
+                        match at(to) {
+                            None => out.push((i, to, None)),
+                            Some(w) => {
+                                if w != white {out.push((i, to, None))}
+                                break;
+                            },
+                        }
+                        sq = to;
+                    }
+                }
+            },
+        }
+    }
+}
+
+// The position after a move, and whether the move resets the 50-move count.
+fn play(placed: &[Placed], (i, to, promotion): (usize, u8, Option<Kind>)) -> ([Placed; 4], usize, bool) {
+    let mut next = [(Kind::King, true, 0); 4];
+    let mut n = 0;
+    let mut zeroing = placed[i].0 == Kind::Pawn;
+
+    for (j, &(kind, white, sq)) in placed.iter().enumerate() {
+        if j != i && sq == to {
+            zeroing = true;
+            continue;
+        }
+        next[n] = if j == i {(promotion.unwrap_or(kind), white, to)} else {(kind, white, sq)};
+        n += 1;
+    }
+
+    (next, n, zeroing)
+}
+
+// A legal move from a position: the index of the result if it has the same material, and
+// otherwise its WDL, looked up in the tables solved before.
+struct Child {
+    same: Option<usize>,
+    wdl: i8,
+    zeroing: bool,
+}
+
+fn children(placed: &[Placed], white: bool, solved: &HashMap<String, Solved>, buf: &mut Vec<(usize, u8, Option<Kind>)>, out: &mut Vec<Child>) {
+    moves(placed, white, buf);
+    out.clear();
+
+    for &m in buf.iter() {
+        let (next, n, zeroing) = play(placed, m);
+        let next = &next[..n];
+        if in_check(next, white) {continue}
+
+        out.push(if n == placed.len() && m.2.is_none() {
+            Child { same: Some(Solved::index(next, !white)), wdl: UNKNOWN, zeroing }
+        } else {
+            Child { same: None, wdl: wdl_of(solved, next, !white), zeroing }
+        });
+    }
+}
+
+fn solve(stem: &str, solved: &HashMap<String, Solved>) -> Solved {
+    let pieces = pieces(stem);
+    let n = pieces.len();
+    let mut t = Solved { pieces, wdl: vec![UNKNOWN; 2 << (6 * n)], dtz: vec![0; 2 << (6 * n)] };
+    let mut mated = vec![false; t.wdl.len()];
+    let (mut buf, mut out) = (Vec::new(), Vec::new());
+
+    let mut pending: Vec<u32> = Vec::new();
+    for idx in 0..t.wdl.len() {
+        let (placed, white) = t.placed(idx);
+        let placed = &placed[..n];
+
+        let overlapping = (0..n).any(|i| (i + 1..n).any(|j| placed[i].2 == placed[j].2));
+        let back_rank = placed.iter().any(|p| p.0 == Kind::Pawn && (p.2 < 8 || p.2 >= 56));
+
+        if overlapping || back_rank || in_check(placed, !white) {
+            t.wdl[idx] = ILLEGAL;
+        } else {
+            pending.push(idx as u32);
+        }
+    }
+
+    // WDL: a win once a move reaches a loss, a loss once every move reaches a win, and a draw
+    // for whatever is left when nothing changes any more.
+    loop {
+        let before = pending.len();
+
+        pending.retain(|&idx| {
+            let (placed, white) = t.placed(idx as usize);
+            children(&placed[..n], white, solved, &mut buf, &mut out);
+
+            let (mut best, mut unknown) = (None, false);
+            for c in &out {
+                let wdl = c.same.map_or(c.wdl, |i| t.wdl[i]);
+                if wdl == UNKNOWN {unknown = true} else {best = best.max(Some(-wdl))}
+            }
+
+            let wdl = match best {
+                _ if out.is_empty() && in_check(&placed[..n], white) => LOSS as i8,
+                None if out.is_empty() => DRAW as i8,
+                Some(wdl) if wdl == WIN as i8 || !unknown => wdl,
+                _ => return true,
+            };
+
+            t.wdl[idx as usize] = wdl;
+            mated[idx as usize] = out.is_empty() && wdl == LOSS as i8;
+            false
+        });
+
+        if pending.len() == before {break}
+    }
+    for idx in pending {t.wdl[idx as usize] = DRAW as i8}
+
+    // DTZ, a ply further each round: wins by the fastest move to a loss, where mating or a
+    // zeroing move counts as one ply, and losses by the slowest move to a win.
+    let mut pending: Vec<u32> = (0..t.wdl.len() as u32).filter(|&i| t.wdl[i as usize] == WIN as i8 || t.wdl[i as usize] == LOSS as i8).collect();
+    let mut plies = 1;
+
+    while !pending.is_empty() {
+        pending.retain(|&idx| {
+            let (placed, white) = t.placed(idx as usize);
+            let win = t.wdl[idx as usize] == WIN as i8;
+            children(&placed[..n], white, solved, &mut buf, &mut out);
+
+            let mut longest = 0;
+            for c in &out {
+                let wdl = c.same.map_or(c.wdl, |i| t.wdl[i]);
+                let ply = match c.same {
+                    _ if c.zeroing => 1,
+                    Some(i) if mated[i] => 1,
+                    Some(i) if t.dtz[i] != 0 => t.dtz[i].abs() + 1,
+                    _ => i16::MAX,
+                };
+
+                if win && wdl == LOSS as i8 && ply <= plies {
+                    t.dtz[idx as usize] = plies;
+                    return false;
+                }
+                longest = longest.max(ply);
+            }
+
+            if win || longest > plies {return true}
+            t.dtz[idx as usize] = -plies;
+            false
+        });
+
+        plies += 1;
+        assert!(plies < 1000, "{}: DTZ does not converge", stem);
+    }
+
+    t
+}
+
+// ---------------------------------------------------------------------------------------------
+// Writing
+//
+// Nothing here comes from the prober: the index follows the original generator, with its
+// square tables spelled out, each side to move and file gets a piece order of its own, and the
+// values are compressed into symbol pairs before they are Huffman coded.
+
+const MAGIC: [[u8; 4]; 2] = [[0x71, 0xE8, 0x23, 0x5D], [0xD7, 0x66, 0x0C, 0xA5]];
+
+// The first piece of a table without pawns is brought into the a1-d1-d4 triangle, numbered off
+// the diagonal first.
+const TRIANGLE: [[u64; 8]; 8] = [
+    [6, 0, 1, 2, 2, 1, 0, 6],
+    [0, 7, 3, 4, 4, 3, 7, 0],
+    [1, 3, 8, 5, 5, 8, 3, 1],
+    [2, 4, 5, 9, 9, 5, 4, 2],
+    [2, 4, 5, 9, 9, 5, 4, 2],
+    [1, 3, 8, 5, 5, 8, 3, 1],
+    [0, 7, 3, 4, 4, 3, 7, 0],
+    [6, 0, 1, 2, 2, 1, 0, 6],
+];
+
+// The squares below the a1-h8 diagonal, and those on it after them.
+const LOWER: [[u64; 8]; 8] = [
+    [28,  0,  1,  2,  3,  4,  5,  6],
+    [ 0, 29,  7,  8,  9, 10, 11, 12],
+    [ 1,  7, 30, 13, 14, 15, 16, 17],
+    [ 2,  8, 13, 31, 18, 19, 20, 21],
+    [ 3,  9, 14, 18, 32, 22, 23, 24],
+    [ 4, 10, 15, 19, 22, 33, 25, 26],
+    [ 5, 11, 16, 20, 23, 25, 34, 27],
+    [ 6, 12, 17, 21, 24, 26, 27, 35],
+];
+
+// The squares of the a1-h8 diagonal, then those of the a8-h1 one.
+const DIAG: [[u64; 8]; 8] = [
+    [ 0,  0,  0,  0,  0,  0,  0,  8],
+    [ 0,  1,  0,  0,  0,  0,  9,  0],
+    [ 0,  0,  2,  0,  0, 10,  0,  0],
+    [ 0,  0,  0,  3, 11,  0,  0,  0],
+    [ 0,  0,  0, 12,  4,  0,  0,  0],
+    [ 0,  0, 13,  0,  0,  5,  0,  0],
+    [ 0, 14,  0,  0,  0,  0,  6,  0],
+    [15,  0,  0,  0,  0,  0,  0,  7],
+];
+
+// Above the a1-h8 diagonal when positive.
+fn off_diagonal(sq: u8) -> i32 {
+    (sq >> 3) as i32 - (sq & 7) as i32
+}
+
+fn choose(n: u64, k: u64) -> u64 {
+    (0..k).fold(1, |c, i| c * n.saturating_sub(i) / (i + 1))
+}
+
+// How the positions of one side to move and file are indexed: the piece codes in the order they
+// are encoded, the size of each group of pieces by where it starts, and each group's factor.
+struct Layout {
+    pawns: bool,
+    order: u8,
+    pieces: Vec<u8>,
+    norm: Vec<usize>,
+    factor: Vec<u64>,
+    size: u64,
+}
+
+impl Layout {
+    // The leading group is the pawn, as the fixtures never have more than one, or the first three
+    // pieces; after it pieces of one kind and colour are grouped. `order` is where the leading
+    // group goes among the factors.
+    fn new(pieces: Vec<u8>, order: u8, pawns: bool) -> Self {
+        let n = pieces.len();
+        let mut norm = vec![0; n];
+        norm[0] = if pawns {1} else {3};
+
+        let mut i = norm[0];
+        while i < n {
+            norm[i] = pieces[i..].iter().take_while(|&&p| p == pieces[i]).count();
+            i += norm[i];
+        }
+
+        let mut factor = vec![0; n];
+        let (mut size, mut free) = (1, 64 - norm[0] as u64);
+        let (mut i, mut k) = (norm[0], 0);
+
+        while i < n || k == order {
+            if k == order {
+                factor[0] = size;
+                size *= if pawns {6} else {31332};
+            } else {
+                factor[i] = size;
+                size *= choose(free, norm[i] as u64);
+                free -= norm[i] as u64;
+                i += norm[i];
+            }
+            k += 1;
+        }
+
+        Layout { pawns, order, pieces, norm, factor, size }
+    }
+
+    // The index of the pieces on `squares`, given in the layout's order.
+    fn index(&self, squares: &[u8]) -> u64 {
+        let mut sq = squares.to_vec();
+        if sq[0] & 4 != 0 {sq.iter_mut().for_each(|s| *s ^= 7)}
+
+        let mut idx = if self.pawns {
+            (sq[0] >> 3) as u64 - 1
+        } else {
+            if sq[0] & 32 != 0 {sq.iter_mut().for_each(|s| *s ^= 56)}
+            if sq[..3].iter().find(|&&s| off_diagonal(s) != 0).is_some_and(|&s| off_diagonal(s) > 0) {
+                sq.iter_mut().for_each(|s| *s = (*s >> 3) | (*s & 7) << 3);
+            }
+
+            let at = |table: &[[u64; 8]; 8], sq: u8| table[(sq >> 3) as usize][(sq & 7) as usize];
+            let (a, b, c) = (sq[0], sq[1], sq[2]);
+            let (b_adjust, c_adjust) = ((b > a) as u64, (c > a) as u64 + (c > b) as u64);
+
+            if off_diagonal(a) != 0 {
+                at(&TRIANGLE, a) * 63 * 62 + (b as u64 - b_adjust) * 62 + c as u64 - c_adjust
+            } else if off_diagonal(b) != 0 {
+                6 * 63 * 62 + at(&DIAG, a) * 28 * 62 + at(&LOWER, b) * 62 + c as u64 - c_adjust
+            } else if off_diagonal(c) != 0 {
+                6 * 63 * 62 + 4 * 28 * 62 + at(&DIAG, a) * 7 * 28 + (at(&DIAG, b) - b_adjust) * 28 + at(&LOWER, c)
+            } else {
+                6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + at(&DIAG, a) * 7 * 6 + (at(&DIAG, b) - b_adjust) * 6 + at(&DIAG, c) - c_adjust
+            }
+        };
+        idx *= self.factor[0];
+
+        let mut i = self.norm[0];
+        while i < sq.len() {
+            let len = self.norm[i];
+            sq[i..i + len].sort_unstable();
+
+            for m in i..i + len {
+                let below = sq[..i].iter().filter(|&&s| s < sq[m]).count() as u64;
+                idx += choose(sq[m] as u64 - below, (m - i + 1) as u64) * self.factor[i];
+            }
+            i += len;
+        }
+
+        idx
+    }
+}
+
+const BLOCK_SIZE: u8 = 5;
+const SPAN: u8 = 7;
+
+// The sizes, Huffman code and blocks for the values of one side to move and file.
+struct Section {
+    sizes: Vec<u8>,
+    sparse_index: Vec<u8>,
+    block_length: Vec<u8>,
+    data: Vec<u8>,
+}
+
+fn compress(values: &[u16], flags: u8) -> Section {
+    if values.iter().all(|&v| v == values[0]) {
+        return Section { sizes: vec![flags | 0x80, values[0] as u8], sparse_index: Vec::new(), block_length: Vec::new(), data: Vec::new() };
+    }
+
+    // Symbols as (left, right, values): a single value has 0xFFF on the right. The commonest pair
+    // of neighbours becomes a symbol of its own while that is worth it, up to 256 values a symbol.
+    let mut leaves = values.to_vec();
+    leaves.sort_unstable();
+    leaves.dedup();
+
+    let mut symbols: Vec<(u16, u16, usize)> = leaves.iter().map(|&v| (v, 0xFFF, 1)).collect();
+    let mut seq: Vec<u16> = values.iter().map(|v| leaves.binary_search(v).unwrap() as u16).collect();
+
+    while symbols.len() < 0xFFF {
+        let mut counts: HashMap<(u16, u16), usize> = HashMap::new();
+        for w in seq.windows(2) {
+            if symbols[w[0] as usize].2 + symbols[w[1] as usize].2 <= 256 {*counts.entry((w[0], w[1])).or_default() += 1}
+        }
+
+        let Some((pair, count)) = counts.into_iter().max_by_key(|&(pair, count)| (count, Reverse(pair))) else {break};
+        if count < 4 {break}
+
+        symbols.push((pair.0, pair.1, symbols[pair.0 as usize].2 + symbols[pair.1 as usize].2));
+        let symbol = (symbols.len() - 1) as u16;
+
+        let mut next = Vec::with_capacity(seq.len());
+        let mut i = 0;
+        while i < seq.len() {
+            if seq[i..].starts_with(&[pair.0, pair.1]) {
+                next.push(symbol);
+                i += 2;
+            } else {
+                next.push(seq[i]);
+                i += 1;
+            }
+        }
+        seq = next;
+    }
+
+    // Huffman code lengths, from merging the two lightest sets of symbols until one is left.
+    let mut weight = vec![0u64; symbols.len()];
+    for &s in &seq {weight[s as usize] += 1}
+
+    let mut len = vec![0usize; symbols.len()];
+    let mut heap: BinaryHeap<Reverse<(u64, Vec<usize>)>> = (0..symbols.len()).filter(|&s| weight[s] > 0).map(|s| Reverse((weight[s], vec![s]))).collect();
+    if heap.len() == 1 {len[heap.peek().unwrap().0 .1[0]] = 1}
+
+    while heap.len() > 1 {
+        let (Reverse((a, mut merged)), Reverse((b, other))) = (heap.pop().unwrap(), heap.pop().unwrap());
+        merged.extend(other);
+        for &s in &merged {len[s] += 1}
+        heap.push(Reverse((a + b, merged)));
+    }
+
+    // Canonical codes: the longest come first, counting up from zero and halving on the way to
+    // each shorter length. Symbols only used inside others are numbered after the coded ones.
+    let mut number: Vec<usize> = (0..symbols.len()).collect();
+    number.sort_by_key(|&s| (len[s] == 0, Reverse(len[s]), s));
+    let mut renumbered = vec![0; symbols.len()];
+    for (n, &s) in number.iter().enumerate() {renumbered[s] = n}
+
+    let coded = number.iter().take_while(|&&s| len[s] > 0).count();
+    let (max_len, min_len) = (len[number[0]], len[number[coded - 1]]);
+
+    let mut code = vec![0u64; symbols.len()];
+    let (mut next, mut last) = (0u64, max_len);
+    for &s in &number[..coded] {
+        next >>= last - len[s];
+        code[s] = next;
+        (next, last) = (next + 1, len[s]);
+    }
+
+    // Whole codes to a block, from the top bit down.
+    let block_bits = 8 << BLOCK_SIZE;
+    let mut blocks: Vec<(usize, Vec<u8>)> = Vec::new();
+    let (mut bit, mut value) = (block_bits, 0);
+
+    for &s in &seq {
+        let (s, l) = (s as usize, len[s as usize]);
+        if bit + l > block_bits {
+            blocks.push((value, vec![0; 1 << BLOCK_SIZE]));
+            bit = 0;
+        }
+
+        let block = &mut blocks.last_mut().unwrap().1;
+        for b in 0..l {
+            if code[s] >> (l - 1 - b) & 1 == 1 {block[(bit + b) / 8] |= 0x80 >> ((bit + b) % 8)}
+        }
+        bit += l;
+        value += symbols[s].2;
+    }
+
+    let mut sizes = vec![flags, BLOCK_SIZE, SPAN, 0];
+    sizes.extend((blocks.len() as u32).to_le_bytes());
+    sizes.extend([max_len as u8, min_len as u8]);
+    for l in min_len..=max_len {
+        let first = number[..coded].iter().filter(|&&s| len[s] > l).count();
+        sizes.extend((first as u16).to_le_bytes());
+    }
+    sizes.extend((symbols.len() as u16).to_le_bytes());
+    for &s in &number {
+        let (left, right, _) = symbols[s];
+        let (left, right) = if right == 0xFFF {(left as usize, 0xFFF)} else {(renumbered[left as usize], renumbered[right as usize])};
+        sizes.extend([left as u8, (left >> 8 | right << 4) as u8, (right >> 4) as u8]);
+    }
+    if symbols.len() % 2 == 1 {sizes.push(0)}
+
+    // Every span has an entry for its middle value: the block it is in and how far into it.
+    let starts: Vec<usize> = blocks.iter().map(|b| b.0).collect();
+    let span = 1 << SPAN;
+    let mut sparse_index = Vec::new();
+
+    for k in 0..values.len().div_ceil(span) {
+        let middle = k * span + span / 2;
+        let block = starts.iter().rposition(|&s| s <= middle.min(values.len() - 1)).unwrap();
+        sparse_index.extend((block as u32).to_le_bytes());
+        sparse_index.extend(u16::try_from(middle - starts[block]).unwrap().to_le_bytes());
+    }
+
+    let ends = starts.iter().skip(1).copied().chain([values.len()]);
+    let block_length = starts.iter().zip(ends).flat_map(|(a, b)| u16::try_from(b - a - 1).unwrap().to_le_bytes()).collect();
+
+    Section { sizes, sparse_index, block_length, data: blocks.into_iter().flat_map(|b| b.1).collect() }
+}
+
+fn encode(t: &Solved, stem: &str, dtz: bool, dtz_sides: [usize; 4], mapped: bool) -> Vec<u8> {
+    let code = |&(kind, white): &(Kind, bool)| (kind.index() + 1) as u8 + if white {0} else {8};
+    let pawns = t.pieces.iter().any(|p| p.0 == Kind::Pawn);
+    let (white, black) = stem.split_once('v').unwrap();
+    assert_ne!(white, black, "symmetric tables are not written");
+
+    let files = if pawns {4} else {1};
+    let halves = if dtz {1} else {2};
+
+    // After the pawn the pieces are rotated by the side to move, and the pawn's place among the
+    // factors moves on with the side and file, so that no two layouts of a table are alike.
+    let layout = |side: usize, f: usize| {
+        let mut pieces: Vec<u8> = t.pieces.iter().map(code).collect();
+        pieces.sort_by_key(|&c| c != 1);
+
+        let lead = pawns as usize;
+        pieces[lead..].rotate_left(side);
+        let groups = pieces[lead..].chunk_by(|a, b| a == b).count();
+        let order = if pawns {(side + f) % (groups + 1)} else {0};
+
+        Layout::new(pieces, order as u8, pawns)
+    };
+
+    let layouts: Vec<Vec<Layout>> = (0..files).map(|f| {
+        (0..halves).map(|side| layout(if dtz {dtz_sides[f]} else {side}, f)).collect()
+    }).collect();
+    let mut values: Vec<Vec<Vec<Option<i16>>>> = layouts.iter().map(|l| l.iter().map(|l| vec![None; l.size as usize]).collect()).collect();
+
+    for idx in 0..t.wdl.len() {
+        if t.wdl[idx] == ILLEGAL {continue}
+
+        let (placed, white) = t.placed(idx);
+        let placed = &placed[..t.pieces.len()];
+        let side = !white as usize;
+        let f = placed.iter().find(|p| p.0 == Kind::Pawn).map_or(0, |p| (p.2 & 7).min(7 - (p.2 & 7)) as usize);
+
+        // DTZ is kept for one side to move, and means nothing in draws.
+        let (half, value) = match dtz {
+            false => (side, t.wdl[idx] as i16),
+            true if side != dtz_sides[f] || t.dtz[idx] == 0 => continue,
+            true => (0, t.dtz[idx]),
+        };
+
+        let l = &layouts[f][half];
+        let mut taken = [false; 4];
+        let squares: Vec<u8> = l.pieces.iter().map(|&c| {
+            let i = (0..placed.len()).find(|&i| !taken[i] && code(&(placed[i].0, placed[i].1)) == c).unwrap();
+            taken[i] = true;
+            placed[i].2
+        }).collect();
+
+        let slot = &mut values[f][half][l.index(&squares) as usize];
+        assert!(slot.is_none_or(|v| v == value), "{}: positions with one index differ", stem);
+        *slot = Some(value);
+    }
+
+    let mut sections = Vec::new();
+    let mut maps = Vec::new();
+
+    for (f, halves) in values.iter().enumerate() {
+        for values in halves {
+            // WDL is kept as wdl + 2, and DTZ in plies less one, through lists of the values for
+            // wins and for losses when mapped. Cursed results never come up in these tables.
+            let (flags, stored): (u8, Vec<Option<u16>>) = if !dtz {
+                (0, values.iter().map(|v| v.map(|v| (v + 2) as u16)).collect())
+            } else if mapped {
+                let list = |sign: i16| {
+                    let mut list: Vec<i16> = values.iter().flatten().filter(|v| v.signum() == sign).map(|v| v.abs() - 1).collect();
+                    list.sort_unstable();
+                    list.dedup();
+                    list
+                };
+                let (wins, losses) = (list(1), list(-1));
+
+                for list in [&wins, &losses, &Vec::new(), &Vec::new()] {
+                    maps.push(list.len() as u8);
+                    maps.extend(list.iter().map(|&v| v as u8));
+                }
+
+                let stored = values.iter().map(|v| v.map(|v| {
+                    (if v > 0 {&wins} else {&losses}).binary_search(&(v.abs() - 1)).unwrap() as u16
+                })).collect();
+                (dtz_sides[f] as u8 | 2 | 4 | 8, stored)
+            } else {
+                (dtz_sides[f] as u8 | 4 | 8, values.iter().map(|v| v.map(|v| (v.abs() - 1) as u16)).collect())
+            };
+
+            // Indices no position maps to take the value before them, which keeps runs together.
+            let mut last = stored.iter().flatten().next().copied().unwrap_or(0);
+            let filled: Vec<u16> = stored.iter().map(|v| {
+                last = v.unwrap_or(last);
+                last
+            }).collect();
+
+            sections.push(compress(&filled, flags));
+        }
+    }
+
+    let mut bytes = MAGIC[dtz as usize].to_vec();
+    bytes.push(1 | (pawns as u8) << 1);
+
+    for l in &layouts {
+        let (a, b) = (&l[0], &l[l.len() - 1]);
+        bytes.push(a.order | b.order << 4);
+        bytes.extend(a.pieces.iter().zip(&b.pieces).map(|(p, q)| p | q << 4));
+    }
+    if bytes.len() % 2 == 1 {bytes.push(0)}
+
+    for s in &sections {bytes.extend(&s.sizes)}
+    if dtz {
+        bytes.extend(maps);
+        if bytes.len() % 2 == 1 {bytes.push(0)}
+    }
+    for s in &sections {bytes.extend(&s.sparse_index)}
+    for s in &sections {bytes.extend(&s.block_length)}
+    for s in &sections {
+        bytes.resize(bytes.len().next_multiple_of(64), 0);
+        bytes.extend(&s.data);
+    }
+
+    bytes
+}
+
+// Writes the tables under test-data/syzygy: `cargo test --release generate -- --ignored`.
+#[test]
+#[ignore]
+fn generate() {
+    let mut solved = HashMap::new();
+
+    for (stem, dtz_sides, mapped) in TABLES {
+        let t = solve(stem, &solved);
+
+        for dtz in [false, true] {
+            let path = format!("{}/{}.{}", DIR, stem, if dtz {"rtbz"} else {"rtbw"});
+            std::fs::write(&path, encode(&t, stem, dtz, dtz_sides, mapped)).unwrap();
+        }
+        solved.insert(stem.to_string(), t);
+    }
+}
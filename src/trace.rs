@@ -0,0 +1,117 @@
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Term {
+    Material,
+    Pst,
+    Pawns,
+    PassedPawns,
+    KingSafety,
+    Mobility,
+    Outposts,
+    BishopPair,
+    Rooks,
+    Trapped,
+}
+
+pub const TERMS: [Term; 10] = [
+    Term::Material,
+    Term::Pst,
+    Term::Pawns,
+    Term::PassedPawns,
+    Term::KingSafety,
+    Term::Mobility,
+    Term::Outposts,
+    Term::BishopPair,
+    Term::Rooks,
+    Term::Trapped,
+];
+
+impl Term {
+    pub fn name(self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::Pst => "PST",
+            Term::Pawns => "Pawns",
+            Term::PassedPawns => "Passed pawns",
+            Term::KingSafety => "King safety",
+            Term::Mobility => "Mobility",
+            Term::Outposts => "Outposts",
+            Term::BishopPair => "Bishop pair",
+            Term::Rooks => "Rooks",
+            Term::Trapped => "Trapped pieces",
+        }
+    }
+}
+
+// Scores are stored from each side's own point of view: terms[term][side] = (mg, eg), side 0 is white.
+#[derive(Copy, Clone, Debug)]
+pub struct Trace {
+    pub detailed: bool,
+    pub phase: f32,
+    pub terms: [[(f32, f32); 2]; TERMS.len()],
+    pub squares: [[(f32, f32); 8]; 8],
+}
+
+impl Trace {
+    pub fn new(detailed: bool) -> Self {
+        Trace {
+            detailed,
+            phase: 1.,
+            terms: [[(0., 0.); 2]; TERMS.len()],
+            squares: [[(0., 0.); 8]; 8],
+        }
+    }
+
+    pub fn add(&mut self, term: Term, white: bool, mg: f32, eg: f32) {
+        let t = &mut self.terms[term as usize][!white as usize];
+        t.0 += mg;
+        t.1 += eg;
+    }
+
+    pub fn add_at(&mut self, term: Term, white: bool, mg: f32, eg: f32, x: usize, y: usize) {
+        self.add(term, white, mg, eg);
+
+        if self.detailed {
+            self.squares[y][x].0 += mg;
+            self.squares[y][x].1 += eg;
+        }
+    }
+
+    pub fn blend(&self, (mg, eg): (f32, f32)) -> f32 {
+        mg * self.phase + eg * (1. - self.phase)
+    }
+
+    pub fn side(&self, term: Term, white: bool) -> f32 {
+        self.blend(self.terms[term as usize][!white as usize])
+    }
+
+    pub fn term(&self, term: Term) -> f32 {
+        self.side(term, true) - self.side(term, false)
+    }
+
+    pub fn total(&self) -> f32 {
+        TERMS.iter().map(|&t| self.term(t)).sum()
+    }
+
+    pub fn square(&self, x: usize, y: usize) -> f32 {
+        self.blend(self.squares[y][x])
+    }
+
+    pub fn report(&self) -> String {
+        let mut out = format!(
+            "{:<16}{:>9}{:>9}{:>9}{:>9}{:>9}\n",
+            "Term", "W mg", "W eg", "B mg", "B eg", "Total"
+        );
+
+        for &term in TERMS.iter() {
+            let [w, b] = self.terms[term as usize];
+            out += &format!(
+                "{:<16}{:>9.2}{:>9.2}{:>9.2}{:>9.2}{:>9.2}\n",
+                term.name(), w.0, w.1, b.0, b.1, self.term(term)
+            );
+        }
+
+        out += &format!("\nPhase {:.2}\nTotal {:.2} (white's point of view)\n", self.phase, self.total());
+
+        out
+    }
+}
@@ -0,0 +1,201 @@
+use crate::params::EvalParams;
+use crate::{evaluate_trace, fen, Piece};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::Scope;
+
+struct Sample {
+    board: [[Option<Piece>; 8]; 8],
+    result: f32,
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut input = None;
+    let mut out = String::from("tuned.toml");
+    let mut threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut iterations = 100;
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = |i: usize| args.get(i + 1).cloned().ok_or(format!("{} needs a value", args[i]));
+
+        match args[i].as_str() {
+            "--out" => {out = value(i)?; i += 1},
+            "--threads" => {threads = value(i)?.parse().map_err(|_| "--threads needs a number")?; i += 1},
+            "--iterations" => {iterations = value(i)?.parse().map_err(|_| "--iterations needs a number")?; i += 1},
+            path if input.is_none() => input = Some(path.to_string()),
+            other => return Err(format!("unexpected argument {}", other)),
+        }
+        i += 1;
+    }
+
+    let input = input.ok_or("usage: chess tune <positions> [--out file] [--threads n] [--iterations n]")?;
+    let samples = load(&input)?;
+    let threads = threads.max(1);
+
+    println!("{} positions, {} threads", samples.len(), threads);
+
+    let mut params = *crate::params::get();
+
+    std::thread::scope(|scope| {
+        let pool = Pool::new(scope, &samples, threads);
+        tune(&pool, &mut params, iterations, &out)
+    })?;
+
+    println!("wrote {}", out);
+
+    Ok(())
+}
+
+fn tune(pool: &Pool, params: &mut EvalParams, iterations: usize, out: &str) -> Result<(), String> {
+    let k = fit_k(pool, params);
+    let mut best = pool.error(params, k);
+    println!("K = {:.4}, error = {:.6}", k, best);
+
+    for iteration in 1..=iterations {
+        let mut improved = false;
+        let count = params.fields().iter().map(|(_, v)| v.len()).sum::<usize>();
+
+        for n in 0..count {
+            let original = get(params, n);
+            let step = f32::max(original.abs() * 0.05, 0.01);
+
+            for candidate in [original + step, original - step] {
+                set(params, n, candidate);
+
+                let e = pool.error(params, k);
+                if e < best {
+                    best = e;
+                    improved = true;
+                    break;
+                }
+
+                set(params, n, original);
+            }
+        }
+
+        println!("iteration {}: error = {:.6}", iteration, best);
+        params.save(out)?;
+
+        if !improved {break}
+    }
+
+    Ok(())
+}
+
+fn load(path: &str) -> Result<Vec<Sample>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut samples = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {continue}
+
+        let result = parse_result(line).ok_or(format!("{}:{}: no game result found", path, i + 1))?;
+        let board = fen::parse_board(line).map_err(|e| format!("{}:{}: {}", path, i + 1, e))?;
+
+        samples.push(Sample { board, result });
+    }
+
+    if samples.is_empty() {
+        return Err(format!("{}: no positions", path));
+    }
+
+    Ok(samples)
+}
+
+// Accepts `1-0` / `0-1` / `1/2-1/2` anywhere on the line (EPD `c9 "1-0";` included)
+// or a bracketed score like `[1.0]`, `[0.5]`, `[0.0]`.
+fn parse_result(line: &str) -> Option<f32> {
+    if line.contains("1/2-1/2") {return Some(0.5)}
+    if line.contains("1-0") {return Some(1.)}
+    if line.contains("0-1") {return Some(0.)}
+
+    let start = line.rfind('[')?;
+    let end = line[start..].find(']')? + start;
+
+    line[start + 1..end].trim().parse().ok()
+}
+
+fn get(params: &mut EvalParams, n: usize) -> f32 {
+    let mut n = n;
+
+    for (_, values) in params.fields() {
+        if n < values.len() {return values[n]}
+        n -= values.len();
+    }
+
+    unreachable!()
+}
+
+fn set(params: &mut EvalParams, n: usize, value: f32) {
+    let mut n = n;
+
+    for (_, values) in params.fields() {
+        if n < values.len() {
+            values[n] = value;
+            return;
+        }
+        n -= values.len();
+    }
+}
+
+fn sigmoid(eval: f32, k: f32) -> f32 {
+    1. / (1. + (-k * eval).exp())
+}
+
+// Worker threads that each keep the same slice of the samples for the whole run, so their
+// thread-local pawn tables stay warm from one error evaluation to the next.
+struct Pool {
+    jobs: Vec<Sender<(EvalParams, f32)>>,
+    sums: Vec<Receiver<f64>>,
+    len: usize,
+}
+
+impl Pool {
+    // The workers finish once the pool is dropped.
+    fn new<'scope>(scope: &'scope Scope<'scope, '_>, samples: &'scope [Sample], threads: usize) -> Pool {
+        let chunk = samples.len().div_ceil(threads);
+
+        let (jobs, sums) = samples.chunks(chunk).map(|part| {
+            let (jobs, job) = channel::<(EvalParams, f32)>();
+            let (sum, sums) = channel();
+
+            scope.spawn(move || {
+                for (params, k) in job {
+                    let total = part.iter().map(|s| {
+                        let e = evaluate_trace(&s.board, &params, false).total();
+                        let d = s.result - sigmoid(e, k);
+                        (d * d) as f64
+                    }).sum::<f64>();
+
+                    if sum.send(total).is_err() {break}
+                }
+            });
+
+            (jobs, sums)
+        }).unzip();
+
+        Pool { jobs, sums, len: samples.len() }
+    }
+
+    fn error(&self, params: &EvalParams, k: f32) -> f32 {
+        for jobs in &self.jobs {jobs.send((*params, k)).unwrap()}
+        let total: f64 = self.sums.iter().map(|sums| sums.recv().unwrap()).sum();
+
+        (total / self.len as f64) as f32
+    }
+}
+
+fn fit_k(pool: &Pool, params: &EvalParams) -> f32 {
+    let (mut lo, mut hi) = (0.001f32, 2.);
+
+    for _ in 0..30 {
+        let a = lo + (hi - lo) / 3.;
+        let b = hi - (hi - lo) / 3.;
+
+        if pool.error(params, a) < pool.error(params, b) {hi = b}
+        else {lo = a}
+    }
+
+    (lo + hi) / 2.
+}
@@ -0,0 +1,183 @@
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::clock::{Bonus, TimeControl};
+use crate::params::EvalParams;
+use crate::position::{Move, Position};
+use crate::search::{self, Limits, SearchResult};
+use crate::{book, evaluate_trace, fen, nnue, params, syzygy, Kind, Piece};
+
+// The engine over the Universal Chess Interface, for GUIs that drive it through stdin and stdout.
+// Searches run on a thread of their own so that `stop` is read while they go on.
+pub fn run() {
+    let mut pos = Position::start();
+    // Hashes of the positions played before `pos`.
+    let mut history: Vec<u64> = Vec::new();
+    let mut searching: Option<(Arc<AtomicBool>, JoinHandle<()>)> = None;
+
+    for line in std::io::stdin().lock().lines() {
+        let Ok(line) = line else {break};
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words.first().copied() {
+            Some("uci") => {
+                println!("id name chess");
+                println!("option name Book type string default <empty>");
+                println!("option name BookDepth type spin default 16 min 0 max 1000");
+                println!("option name BookBestOnly type check default false");
+                println!("option name ParamsFile type string default <empty>");
+                println!("option name EvalFile type string default <empty>");
+                println!("option name UseNNUE type check default {}", nnue::enabled());
+                println!("option name SyzygyPath type string default <empty>");
+                println!("uciok");
+            },
+            Some("isready") => println!("readyok"),
+            Some("ucinewgame") => {
+                stop(&mut searching);
+                (pos, history) = (Position::start(), Vec::new());
+            },
+            Some("position") => match position(&words[1..]) {
+                Ok((p, h)) => (pos, history) = (p, h),
+                Err(e) => println!("info string {}", e),
+            },
+            Some("go") => {
+                stop(&mut searching);
+                searching = Some(go(pos, history.clone(), limits(&pos, &words[1..]), words.contains(&"infinite")));
+            },
+            Some("stop") => stop(&mut searching),
+            Some("setoption") => if let Err(e) = set_option(&words[1..]) {println!("info string {}", e)},
+            // Not part of UCI: the evaluation of the current position term by term, as `chess eval` prints it.
+            Some("eval") => {
+                print!("{}", evaluate_trace(&pos.board, &params::get(), false).report());
+                if nnue::enabled() {println!("NNUE  {:.2} (white's point of view)", nnue::evaluate(&pos.board))}
+            },
+            Some("quit") => break,
+            _ => {},
+        }
+    }
+
+    stop(&mut searching);
+}
+
+// `startpos` or `fen <fen>`, then optionally `moves` and the moves played from there.
+fn position(words: &[&str]) -> Result<(Position, Vec<u64>), String> {
+    let moves = words.iter().position(|&w| w == "moves").unwrap_or(words.len());
+
+    let mut pos = match words.first().copied() {
+        Some("startpos") => Position::start(),
+        Some("fen") => fen::parse(&words[1..moves].join(" ")).and_then(|p| p.validate().map(|_| p))?,
+        _ => return Err("position needs startpos or fen".to_string()),
+    };
+    let mut history = Vec::new();
+
+    for &word in words.iter().skip(moves + 1) {
+        let m = pos.legal_moves().into_iter().find(|&m| name(m) == word).ok_or(format!("illegal move {}", word))?;
+        history.push(pos.hash());
+        pos = pos.make(m);
+    }
+
+    Ok((pos, history))
+}
+
+// Fixed depth, nodes or time, or a share of the clock for the side to move; until `stop` otherwise.
+fn limits(pos: &Position, words: &[&str]) -> Limits {
+    let mut limits = Limits::infinite();
+    let (mut left, mut increment, mut moves) = (None, Duration::ZERO, 0);
+
+    for pair in words.windows(2) {
+        let Ok(n) = pair[1].parse::<u64>() else {continue};
+
+        match pair[0] {
+            "depth" => limits.depth = n as u32,
+            "nodes" => limits.nodes = n,
+            "movetime" => limits.time = Some(Duration::from_millis(n)),
+            "wtime" if pos.white => left = Some(Duration::from_millis(n)),
+            "btime" if !pos.white => left = Some(Duration::from_millis(n)),
+            "winc" if pos.white => increment = Duration::from_millis(n),
+            "binc" if !pos.white => increment = Duration::from_millis(n),
+            "movestogo" => moves = n as u32,
+            _ => {},
+        }
+    }
+
+    if let Some(left) = left.filter(|_| limits.time.is_none()) {
+        let bonus = if increment.is_zero() {Bonus::None} else {Bonus::Fischer(increment)};
+        limits.time = Some(TimeControl { base: left, bonus, moves }.budget(left, 0));
+    }
+
+    limits
+}
+
+// The best move is only given once the search is over, and after `stop` when `infinite`, even if
+// the search ends by itself before that.
+fn go(pos: Position, history: Vec<u64>, limits: Limits, infinite: bool) -> (Arc<AtomicBool>, JoinHandle<()>) {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+
+    let handle = std::thread::spawn(move || {
+        let best = book::pick(&pos).or_else(|| search::search_with(&pos, &history, limits, &stopped, info).best);
+        while infinite && !stopped.load(Ordering::Relaxed) {std::thread::sleep(Duration::from_millis(10))}
+
+        match best {
+            Some(m) => println!("bestmove {}", name(m)),
+            None => println!("bestmove 0000"),
+        }
+    });
+
+    (stop, handle)
+}
+
+fn stop(searching: &mut Option<(Arc<AtomicBool>, JoinHandle<()>)>) {
+    if let Some((stop, handle)) = searching.take() {
+        stop.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+}
+
+fn info(r: &SearchResult) {
+    let score = if search::is_mate_score(r.score) {
+        format!("mate {}", r.score.signum() * (search::MATE - r.score.abs() + 1) / 2)
+    } else {
+        format!("cp {}", r.score)
+    };
+    // A mated or stalemated position is reported with no principal variation at all.
+    let pv: String = r.pv.iter().map(|&m| format!(" {}", name(m))).collect();
+    let pv = if pv.is_empty() {pv} else {format!(" pv{}", pv)};
+
+    println!("info depth {} score {} nodes {}{}", r.depth, score, r.nodes, pv);
+}
+
+fn set_option(words: &[&str]) -> Result<(), String> {
+    let value = words.iter().position(|&w| w == "value");
+    let name = words[1.min(words.len())..value.unwrap_or(words.len())].join(" ");
+    let value = value.map_or(String::new(), |i| words[i + 1..].join(" "));
+
+    match name.as_str() {
+        "Book" if value.is_empty() || value == "<empty>" => book::unload(),
+        "Book" => book::load(&value)?,
+        "BookDepth" => book::set_max_depth(value.parse().map_err(|_| "BookDepth needs a number of plies")?),
+        "BookBestOnly" => book::set_best_only(value == "true"),
+        "ParamsFile" if value.is_empty() || value == "<empty>" => params::set(EvalParams::DEFAULT),
+        "ParamsFile" => params::set(EvalParams::load(&value).map_err(|e| format!("invalid parameter file {}", e))?),
+        "EvalFile" if value.is_empty() || value == "<empty>" => nnue::unload(),
+        "EvalFile" => nnue::load(&value).map_err(|e| format!("invalid network {}", e))?,
+        "UseNNUE" => nnue::set_enabled(value == "true"),
+        "SyzygyPath" if value.is_empty() || value == "<empty>" => syzygy::unload(),
+        "SyzygyPath" => {
+            let count = syzygy::init(&value).map_err(|e| format!("invalid tablebase path {}", e))?;
+            println!("info string found {} tablebases (up to {} pieces) in {}", count, syzygy::max_pieces(), value);
+        },
+        _ => return Err(format!("unknown option {}", name)),
+    }
+
+    Ok(())
+}
+
+// Long algebraic notation: `e2e4`, `e1g1` for castling, `e7e8q` for promotions.
+fn name(m: Move) -> String {
+    let promotion = m.4.map(|kind: Kind| fen::piece_char(Piece { kind, white: false }).to_string());
+    fen::square_name((m.0, m.1)) + &fen::square_name((m.2, m.3)) + &promotion.unwrap_or_default()
+}